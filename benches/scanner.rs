@@ -0,0 +1,51 @@
+//! Measures how scanning time grows with the size of the source
+//!
+//! Run with `cargo bench --bench scanner`. For each input size the
+//! scanner runs several times and the fastest run is reported, with the
+//! time per byte; a linear scanner keeps the time per byte flat as the
+//! input grows.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use rlox::scanner::Scanner;
+
+/// a line of source that mixes ASCII, non-ASCII text, comments and numbers
+const UNIT: &str = "var name = \"你好, world\"; // comment\nprint 12.5 * (x + y) >= 3;\n";
+
+/// the input sizes to scan, in kilobytes
+const SIZES_KB: [usize; 5] = [64, 128, 256, 512, 1024];
+
+/// how many times each input is scanned
+const RUNS: usize = 10;
+
+/// the fastest of several scans of the source
+fn fastest_scan(source: &str) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let started = Instant::now();
+            let (tokens, errors) = Scanner::new(black_box(source)).scan_tokens();
+            let elapsed = started.elapsed();
+            assert!(errors.is_empty());
+            black_box(tokens);
+            elapsed
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    println!("{:>8} {:>12} {:>10}", "size", "time", "ns/byte");
+    for kb in SIZES_KB {
+        let source = UNIT.repeat(kb * 1024 / UNIT.len());
+        let elapsed = fastest_scan(&source);
+        println!(
+            "{:>5} KB {:>12.3?} {:>10.2}",
+            kb,
+            elapsed,
+            elapsed.as_nanos() as f64 / source.len() as f64
+        );
+    }
+}
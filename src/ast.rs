@@ -0,0 +1,188 @@
+use std::{cell::Cell, rc::Rc};
+
+use crate::token::OwnedToken;
+
+/// Expressions in the Lox syntax tree
+/// 
+/// # Variants
+/// 
+/// * `Assign` - Assignment to a variable, e.g. `a = 1`
+/// * `Binary` - An infix operator applied to two operands, e.g. `a + b`
+/// * `Call` - A function call; `paren` is the closing parenthesis
+/// * `Get` - A property read, e.g. `point.x`
+/// * `Grouping` - A parenthesized expression
+/// * `Literal` - A number, string, boolean or `nil`
+/// * `Logical` - A short-circuiting `and` / `or`
+/// * `Set` - A property write, e.g. `point.x = 1`
+/// * `Super` - A superclass method access, e.g. `super.init`
+/// * `This` - The `this` keyword
+/// * `Unary` - A prefix operator applied to one operand, e.g. `-a`
+/// * `Variable` - A variable read
+/// 
+/// Variable references (`Assign`, `Super`, `This` and `Variable`) carry a
+/// `Depth` that the resolver fills in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Assign {
+        name: OwnedToken,
+        value: Box<Expr>,
+        depth: Depth,
+    },
+    Binary {
+        left: Box<Expr>,
+        operator: OwnedToken,
+        right: Box<Expr>,
+    },
+    Call {
+        callee: Box<Expr>,
+        paren: OwnedToken,
+        arguments: Vec<Expr>,
+    },
+    Get {
+        object: Box<Expr>,
+        name: OwnedToken,
+    },
+    Grouping {
+        expression: Box<Expr>,
+    },
+    Literal {
+        value: LiteralValue,
+    },
+    Logical {
+        left: Box<Expr>,
+        operator: OwnedToken,
+        right: Box<Expr>,
+    },
+    Set {
+        object: Box<Expr>,
+        name: OwnedToken,
+        value: Box<Expr>,
+    },
+    Super {
+        keyword: OwnedToken,
+        method: OwnedToken,
+        depth: Depth,
+    },
+    This {
+        keyword: OwnedToken,
+        depth: Depth,
+    },
+    Unary {
+        operator: OwnedToken,
+        right: Box<Expr>,
+    },
+    Variable {
+        name: OwnedToken,
+        depth: Depth,
+    },
+}
+
+/// How many scopes out a variable reference finds its variable
+/// 
+/// Unset for a reference to a global variable, or before the resolver
+/// has run. It is written through a shared reference because the
+/// resolver only borrows the tree.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Depth(Cell<Option<usize>>);
+
+impl Depth {
+    /// Returns the number of scopes between the reference and its variable,
+    /// or `None` for a global
+    pub fn get(&self) -> Option<usize> {
+        self.0.get()
+    }
+
+    /// Records the number of scopes between the reference and its variable
+    /// 
+    /// # Arguments
+    /// 
+    /// * `depth` - The scope distance, `0` being the innermost scope
+    pub fn set(&self, depth: usize) {
+        self.0.set(Some(depth));
+    }
+}
+
+/// The value of a literal expression
+/// 
+/// # Variants
+/// 
+/// * `Nil` - The `nil` keyword
+/// * `Bool` - The `true` and `false` keywords
+/// * `Number` - A number literal
+/// * `String` - A string literal, with escapes already decoded
+#[derive(Debug, Clone, PartialEq)]
+pub enum LiteralValue {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(String),
+}
+
+/// Statements and declarations in the Lox syntax tree
+/// 
+/// `for` loops have no variant of their own; the parser desugars them
+/// into `while` loops.
+/// 
+/// # Variants
+/// 
+/// * `Block` - A `{ ... }` block with its own scope
+/// * `Class` - A class declaration, with an optional superclass variable
+/// * `Expression` - An expression evaluated for its side effects
+/// * `Function` - A function declaration
+/// * `If` - A conditional with an optional `else` branch
+/// * `Print` - A `print` statement
+/// * `Return` - A `return` statement with an optional value
+/// * `Var` - A variable declaration with an optional initializer
+/// * `While` - A `while` loop
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Block {
+        statements: Vec<Stmt>,
+    },
+    Class {
+        name: OwnedToken,
+        superclass: Option<Expr>,
+        methods: Vec<Rc<Function>>,
+    },
+    Expression {
+        expression: Expr,
+    },
+    Function(Rc<Function>),
+    If {
+        condition: Expr,
+        then_branch: Box<Stmt>,
+        else_branch: Option<Box<Stmt>>,
+    },
+    Print {
+        expression: Expr,
+    },
+    Return {
+        keyword: OwnedToken,
+        value: Option<Expr>,
+    },
+    Var {
+        name: OwnedToken,
+        initializer: Option<Expr>,
+    },
+    While {
+        condition: Expr,
+        body: Box<Stmt>,
+    },
+}
+
+/// A function or method declaration
+/// 
+/// Declarations are shared through `Rc` so that every closure created
+/// from one can refer to it without copying the body.
+/// 
+/// # Fields
+/// 
+/// * `name` - The function's name
+/// * `params` - The parameter names
+/// * `body` - The statements in the function body
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: OwnedToken,
+    pub params: Vec<OwnedToken>,
+    pub body: Vec<Stmt>,
+}
@@ -0,0 +1,122 @@
+use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+
+use super::{Callable, Interpreter, LoxFunction, RuntimeError, Value};
+use crate::token::OwnedToken;
+
+/// A Lox class
+/// 
+/// Calling a class creates an instance and runs its `init` method, if it
+/// has one, with the call's arguments.
+/// 
+/// # Fields
+/// 
+/// * `name` - The class name
+/// * `superclass` - The class this one inherits from, if any
+/// * `methods` - The methods declared in the class body
+pub struct Class {
+    pub name: String,
+    pub superclass: Option<Rc<Class>>,
+    pub methods: HashMap<String, Rc<LoxFunction>>,
+}
+
+impl Class {
+    /// Looks up a method on the class or its superclasses
+    /// 
+    /// # Arguments
+    /// 
+    /// * `name` - The method name
+    pub fn find_method(&self, name: &str) -> Option<Rc<LoxFunction>> {
+        match self.methods.get(name) {
+            Some(method) => Some(Rc::clone(method)),
+            None => self.superclass.as_ref()?.find_method(name),
+        }
+    }
+}
+
+impl Callable for Class {
+    fn arity(&self) -> usize {
+        self.find_method("init").map_or(0, |init| init.arity())
+    }
+
+    fn trace_name(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+
+    fn call(self: Rc<Self>, interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        let init = self.find_method("init");
+        let instance = Rc::new(RefCell::new(Instance::new(self)));
+        if let Some(init) = init {
+            Rc::new(init.bind(Rc::clone(&instance))).call(interpreter, arguments)?;
+        }
+        Ok(Value::Instance(instance))
+    }
+
+    fn as_class(self: Rc<Self>) -> Option<Rc<Class>> {
+        Some(self)
+    }
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// An instance of a Lox class
+/// 
+/// # Fields
+/// 
+/// * `class` - The class the instance was created from
+/// * `fields` - The instance's fields
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: HashMap<String, Value>,
+}
+
+impl Instance {
+    /// Creates an instance with no fields
+    /// 
+    /// # Arguments
+    /// 
+    /// * `class` - The class the instance is created from
+    pub fn new(class: Rc<Class>) -> Instance {
+        Instance {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+
+    /// Reads a property: a field, or else a method bound to the instance
+    /// 
+    /// # Arguments
+    /// 
+    /// * `instance` - The instance to read from
+    /// * `name` - The identifier token naming the property
+    /// 
+    /// # Returns
+    /// 
+    /// The property's value, or an error if there is no such property
+    pub fn get(instance: &Rc<RefCell<Instance>>, name: &OwnedToken) -> Result<Value, RuntimeError> {
+        if let Some(value) = instance.borrow().fields.get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+        let method = instance.borrow().class.find_method(&name.lexeme);
+        match method {
+            Some(method) => Ok(Value::Callable(Rc::new(method.bind(Rc::clone(instance))))),
+            None => Err(RuntimeError::new(
+                name,
+                &format!("Undefined property '{}'.", name.lexeme),
+            )),
+        }
+    }
+
+    /// Sets a field, creating it if needed
+    /// 
+    /// # Arguments
+    /// 
+    /// * `name` - The field name
+    /// * `value` - The new value
+    pub fn set(&mut self, name: &str, value: Value) {
+        self.fields.insert(name.to_string(), value);
+    }
+}
@@ -0,0 +1,147 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use super::{RuntimeError, Value};
+use crate::token::OwnedToken;
+
+/// A scope that maps variable names to values
+/// 
+/// Scopes form a chain: a name that is not found in a scope is looked up
+/// in its enclosing scope, up to the global scope.
+/// 
+/// # Fields
+/// 
+/// * `values` - The variables defined in this scope
+/// * `enclosing` - The surrounding scope, `None` for the global scope
+#[derive(Debug, Default)]
+pub struct Environment {
+    values: HashMap<String, Value>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    /// Creates a global scope
+    pub fn new() -> Environment {
+        Environment::default()
+    }
+
+    /// Creates a scope nested inside another
+    /// 
+    /// # Arguments
+    /// 
+    /// * `enclosing` - The surrounding scope
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Environment {
+        Environment {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    /// Defines a variable in this scope, replacing any previous definition
+    /// 
+    /// # Arguments
+    /// 
+    /// * `name` - The variable's name
+    /// * `value` - The variable's initial value
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    /// Looks up a variable in this scope or an enclosing one
+    /// 
+    /// # Arguments
+    /// 
+    /// * `name` - The identifier token naming the variable
+    /// 
+    /// # Returns
+    /// 
+    /// The variable's value, or an error if it is not defined
+    pub fn get(&self, name: &OwnedToken) -> Result<Value, RuntimeError> {
+        self.lookup(&name.lexeme).ok_or_else(|| undefined(name))
+    }
+
+    /// Looks up a variable by name in this scope or an enclosing one
+    /// 
+    /// # Arguments
+    /// 
+    /// * `name` - The variable's name
+    /// 
+    /// # Returns
+    /// 
+    /// The variable's value, or `None` if it is not defined
+    pub fn lookup(&self, name: &str) -> Option<Value> {
+        if let Some(value) = self.values.get(name) {
+            return Some(value.clone());
+        }
+        self.enclosing.as_ref()?.borrow().lookup(name)
+    }
+
+    /// Looks up a variable in the scope a given number of steps out
+    /// 
+    /// # Arguments
+    /// 
+    /// * `distance` - How many scopes out the variable is, `0` being this one
+    /// * `name` - The variable's name
+    /// 
+    /// # Returns
+    /// 
+    /// The variable's value, or `None` if that scope does not define it
+    pub fn get_at(&self, distance: usize, name: &str) -> Option<Value> {
+        if distance == 0 {
+            return self.values.get(name).cloned();
+        }
+        self.enclosing.as_ref()?.borrow().get_at(distance - 1, name)
+    }
+
+    /// Assigns to a variable in the scope a given number of steps out
+    /// 
+    /// # Arguments
+    /// 
+    /// * `distance` - How many scopes out the variable is, `0` being this one
+    /// * `name` - The identifier token naming the variable
+    /// * `value` - The new value
+    /// 
+    /// # Returns
+    /// 
+    /// An error if that scope does not define the variable
+    pub fn assign_at(&mut self, distance: usize, name: &OwnedToken, value: Value) -> Result<(), RuntimeError> {
+        if distance > 0 {
+            return match &self.enclosing {
+                Some(enclosing) => enclosing.borrow_mut().assign_at(distance - 1, name, value),
+                None => Err(undefined(name)),
+            };
+        }
+        match self.values.get_mut(&name.lexeme) {
+            Some(slot) => {
+                *slot = value;
+                Ok(())
+            }
+            None => Err(undefined(name)),
+        }
+    }
+
+    /// Assigns to an existing variable in this scope or an enclosing one
+    /// 
+    /// # Arguments
+    /// 
+    /// * `name` - The identifier token naming the variable
+    /// * `value` - The new value
+    /// 
+    /// # Returns
+    /// 
+    /// An error if the variable is not defined
+    pub fn assign(&mut self, name: &OwnedToken, value: Value) -> Result<(), RuntimeError> {
+        if let Some(slot) = self.values.get_mut(&name.lexeme) {
+            *slot = value;
+            return Ok(());
+        }
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+            None => Err(undefined(name)),
+        }
+    }
+}
+
+/// the error for a variable that is not defined in any scope
+pub(super) fn undefined(name: &OwnedToken) -> RuntimeError {
+    RuntimeError::new(name, &format!("Undefined variable '{}'.", name.lexeme))
+}
@@ -0,0 +1,89 @@
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use super::{Callable, Environment, Instance, Interpreter, RuntimeError, Unwind, Value};
+use crate::ast;
+
+/// A function declared in Lox code
+/// 
+/// # Fields
+/// 
+/// * `declaration` - The function's syntax tree
+/// * `closure` - The scope the function was declared in
+/// * `is_initializer` - Whether the function is a class's `init` method
+pub struct LoxFunction {
+    declaration: Rc<ast::Function>,
+    closure: Rc<RefCell<Environment>>,
+    is_initializer: bool,
+}
+
+impl LoxFunction {
+    /// Creates a function that captures the scope it is declared in
+    /// 
+    /// # Arguments
+    /// 
+    /// * `declaration` - The function's syntax tree
+    /// * `closure` - The scope the function was declared in
+    /// * `is_initializer` - Whether the function is a class's `init` method
+    pub fn new(
+        declaration: Rc<ast::Function>,
+        closure: Rc<RefCell<Environment>>,
+        is_initializer: bool,
+    ) -> LoxFunction {
+        LoxFunction {
+            declaration,
+            closure,
+            is_initializer,
+        }
+    }
+
+    /// Creates a copy of the method with `this` bound to an instance
+    /// 
+    /// # Arguments
+    /// 
+    /// * `instance` - The instance `this` refers to
+    pub fn bind(&self, instance: Rc<RefCell<Instance>>) -> LoxFunction {
+        let mut environment = Environment::with_enclosing(Rc::clone(&self.closure));
+        environment.define("this", Value::Instance(instance));
+        LoxFunction::new(
+            Rc::clone(&self.declaration),
+            Rc::new(RefCell::new(environment)),
+            self.is_initializer,
+        )
+    }
+
+    /// the instance an initializer is bound to
+    fn this(&self) -> Value {
+        self.closure.borrow().get_at(0, "this").unwrap_or(Value::Nil)
+    }
+}
+
+impl Callable for LoxFunction {
+    fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    fn trace_name(&self) -> Option<&str> {
+        Some(&self.declaration.name.lexeme)
+    }
+
+    fn call(self: Rc<Self>, interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        let mut environment = Environment::with_enclosing(Rc::clone(&self.closure));
+        for (param, argument) in self.declaration.params.iter().zip(arguments) {
+            environment.define(&param.lexeme, argument);
+        }
+
+        // an initializer always returns its instance, even from a bare `return`
+        match interpreter.execute_block(&self.declaration.body, environment) {
+            Ok(()) | Err(Unwind::Return(_)) if self.is_initializer => Ok(self.this()),
+            Ok(()) => Ok(Value::Nil),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(error)) => Err(error),
+        }
+    }
+}
+
+impl fmt::Display for LoxFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<fn {}>", self.declaration.name.lexeme)
+    }
+}
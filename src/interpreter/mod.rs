@@ -0,0 +1,1063 @@
+/// Classes and instances
+mod class;
+/// Variable scopes
+mod environment;
+/// User-defined functions
+mod function;
+/// Functions implemented in Rust
+mod native;
+/// Runtime value definitions
+mod value;
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    error::Error,
+    fmt,
+    io::{self, Write},
+    rc::Rc,
+};
+
+pub use class::{Class, Instance};
+pub use environment::Environment;
+pub use function::LoxFunction;
+pub use native::{clock, NativeFn, NativeFunction};
+pub use value::{Callable, Value};
+
+use crate::{
+    ast::{Depth, Expr, Function, LiteralValue, Stmt},
+    token::{OwnedToken, TokenType},
+};
+
+/// The most Lox calls that may be in progress at once
+/// 
+/// Every Lox call nests several Rust calls of the tree-walker, so the
+/// limit is set by the host stack. In a debug build the heaviest calls
+/// measured, a method returning from inside nested blocks and loops, take
+/// about 60 KB each, and the 8 MB main thread fits only about 130 of them.
+/// Programs are therefore run on a thread with [`STACK_SIZE`] bytes of
+/// stack, where 1000 such calls use about a quarter of it; the rest is
+/// headroom for expressions nested inside each call.
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+/// The host stack, in bytes, that a thread running the interpreter
+/// needs for [`MAX_CALL_DEPTH`] nested calls
+pub const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// An error raised while executing a program
+/// 
+/// # Fields
+/// 
+/// * `line` - The line of the token where the error occurred, or `0` for
+///   an error from a native function that has not been located yet
+/// * `message` - A description of the error
+/// * `trace` - The call stack when the error occurred, innermost frame
+///   first; filled in as the error leaves the interpreter
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub line: usize,
+    pub message: String,
+    pub trace: Vec<StackFrame>,
+}
+
+impl RuntimeError {
+    /// Creates a runtime error located at a token
+    /// 
+    /// # Arguments
+    /// 
+    /// * `token` - The token where the error occurred
+    /// * `message` - A description of the error
+    pub fn new(token: &OwnedToken, message: &str) -> RuntimeError {
+        RuntimeError {
+            line: token.line,
+            message: message.to_string(),
+            trace: Vec::new(),
+        }
+    }
+
+    /// Creates an error for a native function to return
+    /// 
+    /// The error takes the line of the call that failed.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `message` - A description of the error
+    pub fn native(message: &str) -> RuntimeError {
+        RuntimeError {
+            line: 0,
+            message: message.to_string(),
+            trace: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    /// The message followed by one line per stack frame, innermost first
+    /// 
+    /// A run of identical frames, as deep recursion leaves behind, is shown
+    /// once with the number of times it repeats.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if self.trace.is_empty() {
+            return write!(f, "\n[line {}]", self.line);
+        }
+        let mut frames = self.trace.iter().peekable();
+        while let Some(frame) = frames.next() {
+            let mut repeats = 1;
+            while frames.next_if_eq(&frame).is_some() {
+                repeats += 1;
+            }
+            write!(f, "\n{}", frame)?;
+            if repeats > 1 {
+                write!(f, " (repeated {} times)", repeats)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Error for RuntimeError {}
+
+/// One entry of a runtime error's stack trace
+/// 
+/// # Fields
+/// 
+/// * `function` - The name of the function being executed, or `None` for
+///   top-level code
+/// * `line` - The line being executed in that function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackFrame {
+    pub function: Option<String>,
+    pub line: usize,
+}
+
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.function {
+            Some(name) => write!(f, "[line {}] in {}()", self.line, name),
+            None => write!(f, "[line {}] in script", self.line),
+        }
+    }
+}
+
+/// A call that is being executed
+/// 
+/// # Fields
+/// 
+/// * `function` - The name of the function that was called
+/// * `line` - The line of the call, in the caller
+#[derive(Debug)]
+struct CallFrame {
+    function: String,
+    line: usize,
+}
+
+/// Result type used while evaluating
+type EvalResult<T> = Result<T, RuntimeError>;
+
+/// Why execution of a statement stopped early
+/// 
+/// # Variants
+/// 
+/// * `Error` - A runtime error occurred
+/// * `Return` - A `return` statement is leaving the current function
+#[derive(Debug)]
+enum Unwind {
+    Error(RuntimeError),
+    Return(Value),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(error: RuntimeError) -> Unwind {
+        Unwind::Error(error)
+    }
+}
+
+/// Result type used while executing statements
+type ExecResult = Result<(), Unwind>;
+
+/// Tree-walking interpreter for the Lox language
+/// 
+/// Executes syntax trees directly. Output from `print` goes to the
+/// writer the interpreter was created with. Global variables live as
+/// long as the interpreter, so a REPL can reuse it across lines.
+/// 
+/// Programs must be resolved by a [`Resolver`](crate::resolver::Resolver)
+/// before they run; unresolved variable references are treated as globals.
+/// 
+/// # Fields
+/// 
+/// * `globals` - The outermost scope
+/// * `environment` - The scope of the code being executed
+/// * `frames` - The calls being executed, outermost first
+/// * `output` - Where `print` statements write
+pub struct Interpreter {
+    globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    frames: Vec<CallFrame>,
+    output: Box<dyn Write>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter {
+    /// Creates an interpreter that prints to standard output
+    pub fn new() -> Interpreter {
+        Interpreter::with_output(Box::new(io::stdout()))
+    }
+
+    /// Creates an interpreter that prints to the given writer
+    /// 
+    /// # Arguments
+    /// 
+    /// * `output` - Where `print` statements write
+    pub fn with_output(output: Box<dyn Write>) -> Interpreter {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        Interpreter {
+            environment: Rc::clone(&globals),
+            globals,
+            frames: Vec::new(),
+            output,
+        }
+    }
+
+    /// Defines or replaces a global variable
+    /// 
+    /// # Arguments
+    /// 
+    /// * `name` - The variable's name
+    /// * `value` - The variable's value
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.globals.borrow_mut().define(name, value);
+    }
+
+    /// Executes a program
+    /// 
+    /// # Arguments
+    /// 
+    /// * `statements` - The program to execute
+    /// 
+    /// # Returns
+    /// 
+    /// The first runtime error, which stops execution, with its stack trace
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<(), RuntimeError> {
+        for statement in statements {
+            match self.execute(statement) {
+                Ok(()) => (),
+                // a top-level `return` ends the program
+                Err(Unwind::Return(_)) => return Ok(()),
+                Err(Unwind::Error(mut error)) => {
+                    if error.trace.is_empty() {
+                        error.trace = self.stack_trace(error.line);
+                    }
+                    return Err(error);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// the stack trace for an error at `line` in the innermost call
+    fn stack_trace(&self, line: usize) -> Vec<StackFrame> {
+        let mut trace = Vec::with_capacity(self.frames.len() + 1);
+        let mut line = line;
+        for frame in self.frames.iter().rev() {
+            trace.push(StackFrame {
+                function: Some(frame.function.clone()),
+                line,
+            });
+            line = frame.line;
+        }
+        trace.push(StackFrame { function: None, line });
+        trace
+    }
+
+    /// execute a single statement
+    fn execute(&mut self, statement: &Stmt) -> ExecResult {
+        match statement {
+            Stmt::Block { statements } => {
+                let environment = Environment::with_enclosing(Rc::clone(&self.environment));
+                self.execute_block(statements, environment)?;
+            }
+            Stmt::Expression { expression } => {
+                self.evaluate(expression)?;
+            }
+            Stmt::If { condition, then_branch, else_branch } => {
+                if self.evaluate(condition)?.is_truthy() {
+                    self.execute(then_branch)?;
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(else_branch)?;
+                }
+            }
+            Stmt::Print { expression } => {
+                let value = self.evaluate(expression)?;
+                writeln!(self.output, "{}", value).expect("failed to write program output");
+            }
+            Stmt::While { condition, body } => {
+                while self.evaluate(condition)?.is_truthy() {
+                    self.execute(body)?;
+                }
+            }
+            Stmt::Class { name, superclass, methods } => self.class_declaration(name, superclass.as_ref(), methods)?,
+            Stmt::Function(declaration) => {
+                let function = LoxFunction::new(Rc::clone(declaration), Rc::clone(&self.environment), false);
+                self.environment
+                    .borrow_mut()
+                    .define(&declaration.name.lexeme, Value::Callable(Rc::new(function)));
+            }
+            Stmt::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => self.evaluate(value)?,
+                    None => Value::Nil,
+                };
+                return Err(Unwind::Return(value));
+            }
+            Stmt::Var { name, initializer } => {
+                let value = match initializer {
+                    Some(initializer) => self.evaluate(initializer)?,
+                    None => Value::Nil,
+                };
+                self.environment.borrow_mut().define(&name.lexeme, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// define a class, with a scope binding `super` around its methods when
+    /// it has a superclass
+    fn class_declaration(
+        &mut self,
+        name: &OwnedToken,
+        superclass: Option<&Expr>,
+        methods: &[Rc<Function>],
+    ) -> EvalResult<()> {
+        let superclass = match superclass {
+            Some(expr) => {
+                let value = self.evaluate(expr)?;
+                let class = match value {
+                    Value::Callable(callable) => callable.as_class(),
+                    _ => None,
+                };
+                let Some(class) = class else {
+                    // the parser only accepts an identifier as a superclass
+                    let token = if let Expr::Variable { name: superclass, .. } = expr { superclass } else { name };
+                    return Err(RuntimeError::new(token, "Superclass must be a class."));
+                };
+                Some(class)
+            }
+            None => None,
+        };
+
+        self.environment.borrow_mut().define(&name.lexeme, Value::Nil);
+
+        let enclosing = Rc::clone(&self.environment);
+        if let Some(superclass) = &superclass {
+            let mut environment = Environment::with_enclosing(Rc::clone(&enclosing));
+            environment.define("super", Value::Callable(Rc::clone(superclass) as Rc<dyn Callable>));
+            self.environment = Rc::new(RefCell::new(environment));
+        }
+
+        let methods: HashMap<_, _> = methods
+            .iter()
+            .map(|method| {
+                let is_initializer = method.name.lexeme == "init";
+                let function = LoxFunction::new(Rc::clone(method), Rc::clone(&self.environment), is_initializer);
+                (method.name.lexeme.clone(), Rc::new(function))
+            })
+            .collect();
+
+        self.environment = enclosing;
+        let class = Class {
+            name: name.lexeme.clone(),
+            superclass,
+            methods,
+        };
+        self.environment.borrow_mut().assign(name, Value::Callable(Rc::new(class)))
+    }
+
+    /// execute statements in a new scope, restoring the current scope
+    /// afterwards even if one of them fails
+    fn execute_block(&mut self, statements: &[Stmt], environment: Environment) -> ExecResult {
+        let previous = std::mem::replace(&mut self.environment, Rc::new(RefCell::new(environment)));
+        let result = statements.iter().try_for_each(|statement| self.execute(statement));
+        self.environment = previous;
+        result
+    }
+
+    /// evaluate an expression to a value
+    fn evaluate(&mut self, expr: &Expr) -> EvalResult<Value> {
+        match expr {
+            Expr::Binary { left, operator, right } => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+                binary(operator, left, right)
+            }
+            Expr::Grouping { expression } => self.evaluate(expression),
+            Expr::Literal { value } => Ok(match value {
+                LiteralValue::Nil => Value::Nil,
+                LiteralValue::Bool(b) => Value::Bool(*b),
+                LiteralValue::Number(n) => Value::Number(*n),
+                LiteralValue::String(s) => Value::from(s.as_str()),
+            }),
+            Expr::Logical { left, operator, right } => {
+                let left = self.evaluate(left)?;
+                let short_circuits = match operator.token_type {
+                    TokenType::Or => left.is_truthy(),
+                    _ => !left.is_truthy(),
+                };
+                if short_circuits {
+                    return Ok(left);
+                }
+                self.evaluate(right)
+            }
+            Expr::Unary { operator, right } => {
+                let right = self.evaluate(right)?;
+                match (operator.token_type, right) {
+                    (TokenType::Bang, right) => Ok(Value::Bool(!right.is_truthy())),
+                    (TokenType::Minus, Value::Number(n)) => Ok(Value::Number(-n)),
+                    _ => Err(RuntimeError::new(operator, "Operand must be a number.")),
+                }
+            }
+            Expr::Assign { name, value, depth } => {
+                let value = self.evaluate(value)?;
+                match depth.get() {
+                    Some(distance) => self.environment.borrow_mut().assign_at(distance, name, value.clone())?,
+                    None => self.globals.borrow_mut().assign(name, value.clone())?,
+                }
+                Ok(value)
+            }
+            Expr::Variable { name, depth } => self.look_up_variable(name, depth),
+            Expr::Call { callee, paren, arguments } => {
+                let callee = self.evaluate(callee)?;
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.evaluate(argument))
+                    .collect::<EvalResult<Vec<_>>>()?;
+
+                let Value::Callable(callable) = callee else {
+                    return Err(RuntimeError::new(paren, "Can only call functions and classes."));
+                };
+                if arguments.len() != callable.arity() {
+                    let message = format!("Expected {} arguments but got {}.", callable.arity(), arguments.len());
+                    return Err(RuntimeError::new(paren, &message));
+                }
+                // errors from native functions take the line of the call
+                let native = callable.trace_name().is_none();
+                if let Some(name) = callable.trace_name() {
+                    // deep recursion would overflow the host's own stack
+                    if self.frames.len() == MAX_CALL_DEPTH {
+                        return Err(RuntimeError::new(paren, "Stack overflow."));
+                    }
+                    self.frames.push(CallFrame {
+                        function: name.to_string(),
+                        line: paren.line,
+                    });
+                }
+                let result = callable.call(self, arguments).map_err(|mut error| {
+                    if native && error.line == 0 {
+                        error.line = paren.line;
+                    }
+                    // the innermost call records the whole stack
+                    if error.trace.is_empty() {
+                        error.trace = self.stack_trace(error.line);
+                    }
+                    error
+                });
+                if !native {
+                    self.frames.pop();
+                }
+                result
+            }
+            Expr::Get { object, name } => match self.evaluate(object)? {
+                Value::Instance(instance) => Instance::get(&instance, name),
+                _ => Err(RuntimeError::new(name, "Only instances have properties.")),
+            },
+            Expr::Set { object, name, value } => {
+                let Value::Instance(instance) = self.evaluate(object)? else {
+                    return Err(RuntimeError::new(name, "Only instances have fields."));
+                };
+                let value = self.evaluate(value)?;
+                instance.borrow_mut().set(&name.lexeme, value.clone());
+                Ok(value)
+            }
+            Expr::Super { keyword, method, depth } => {
+                // the resolver puts `this` one scope inside `super`
+                let distance = depth.get().expect("`super` is always a local");
+                let environment = self.environment.borrow();
+                let Some(Value::Callable(superclass)) = environment.get_at(distance, &keyword.lexeme) else {
+                    unreachable!("`super` is always bound to a class");
+                };
+                let Some(Value::Instance(instance)) = environment.get_at(distance - 1, "this") else {
+                    unreachable!("`this` is always bound inside a method");
+                };
+                let superclass = superclass.as_class().expect("`super` is always bound to a class");
+                match superclass.find_method(&method.lexeme) {
+                    Some(found) => Ok(Value::Callable(Rc::new(found.bind(instance)))),
+                    None => Err(RuntimeError::new(
+                        method,
+                        &format!("Undefined property '{}'.", method.lexeme),
+                    )),
+                }
+            }
+            Expr::This { keyword, depth } => self.look_up_variable(keyword, depth),
+        }
+    }
+
+    /// read a variable from the scope the resolver bound it to, or from the
+    /// globals if it was left unresolved
+    fn look_up_variable(&self, name: &OwnedToken, depth: &Depth) -> EvalResult<Value> {
+        match depth.get() {
+            Some(distance) => self
+                .environment
+                .borrow()
+                .get_at(distance, &name.lexeme)
+                .ok_or_else(|| environment::undefined(name)),
+            None => self.globals.borrow().get(name),
+        }
+    }
+}
+
+/// apply a binary operator to two evaluated operands
+fn binary(operator: &OwnedToken, left: Value, right: Value) -> EvalResult<Value> {
+    use Value::{Bool, Number};
+
+    let value = match (operator.token_type, &left, &right) {
+        (TokenType::EqualEqual, _, _) => Bool(left == right),
+        (TokenType::BangEqual, _, _) => Bool(left != right),
+        (TokenType::Plus, Number(a), Number(b)) => Number(a + b),
+        (TokenType::Plus, Value::String(a), Value::String(b)) => {
+            Value::String(Rc::from(format!("{}{}", a, b)))
+        }
+        (TokenType::Plus, _, _) => {
+            return Err(RuntimeError::new(
+                operator,
+                "Operands must be two numbers or two strings.",
+            ))
+        }
+        (TokenType::Minus, Number(a), Number(b)) => Number(a - b),
+        (TokenType::Star, Number(a), Number(b)) => Number(a * b),
+        (TokenType::Slash, Number(a), Number(b)) => Number(a / b),
+        (TokenType::Greater, Number(a), Number(b)) => Bool(a > b),
+        (TokenType::GreaterEqual, Number(a), Number(b)) => Bool(a >= b),
+        (TokenType::Less, Number(a), Number(b)) => Bool(a < b),
+        (TokenType::LessEqual, Number(a), Number(b)) => Bool(a <= b),
+        _ => return Err(RuntimeError::new(operator, "Operands must be numbers.")),
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::{parser::Parser, resolver::Resolver, scanner::Scanner};
+
+    // 辅助结构：可以在测试中读取内容的输出缓冲区
+    #[derive(Clone, Default)]
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // 辅助函数：运行程序，返回输出和运行时错误
+    fn run(source: &str) -> (String, Option<RuntimeError>) {
+        let (tokens, errors) = Scanner::new(source).scan_tokens();
+        assert!(errors.is_empty(), "测试源代码不应该有词法错误");
+        let statements = Parser::new(tokens).parse().expect("测试源代码不应该有语法错误");
+        Resolver::new().resolve(&statements).expect("测试源代码不应该有绑定错误");
+
+        let output = SharedOutput::default();
+        let mut interpreter = Interpreter::with_output(Box::new(output.clone()));
+        let error = interpreter.interpret(&statements).err();
+        let text = String::from_utf8(output.0.borrow().clone()).unwrap();
+        (text, error)
+    }
+
+    // 辅助函数：运行程序并断言没有运行时错误
+    fn output(source: &str) -> String {
+        let (text, error) = run(source);
+        assert_eq!(error, None);
+        text
+    }
+
+    // 辅助函数：运行程序并返回运行时错误
+    fn runtime_error(source: &str) -> RuntimeError {
+        run(source).1.expect("应该产生运行时错误")
+    }
+
+    #[test]
+    fn test_print_literals() {
+        assert_eq!(
+            output("print nil; print true; print false; print 1; print 2.5; print \"hi\";"),
+            "nil\ntrue\nfalse\n1\n2.5\nhi\n"
+        );
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(output("print 1 + 2 * 3;"), "7\n");
+        assert_eq!(output("print (1 + 2) * 3;"), "9\n");
+        assert_eq!(output("print 10 - 4 - 3;"), "3\n");
+        assert_eq!(output("print 7 / 2;"), "3.5\n");
+        assert_eq!(output("print -(3 - 5);"), "2\n");
+        assert_eq!(output("print 1 / 0;"), "inf\n");
+        assert_eq!(output("print 0.1 + 0.2;"), "0.30000000000000004\n");
+    }
+
+    #[test]
+    fn test_string_concatenation() {
+        assert_eq!(output("print \"foo\" + \"bar\";"), "foobar\n");
+        assert_eq!(output("print \"\" + \"\";"), "\n");
+    }
+
+    #[test]
+    fn test_comparison() {
+        assert_eq!(output("print 1 < 2; print 2 <= 2; print 3 > 4; print 3 >= 4;"), "true\ntrue\nfalse\nfalse\n");
+    }
+
+    #[test]
+    fn test_equality() {
+        assert_eq!(output("print nil == nil;"), "true\n");
+        assert_eq!(output("print 1 == 1;"), "true\n");
+        assert_eq!(output("print \"a\" == \"a\";"), "true\n");
+        assert_eq!(output("print \"a\" != \"b\";"), "true\n");
+        assert_eq!(output("print 1 == \"1\";"), "false\n");
+        assert_eq!(output("print nil == false;"), "false\n");
+        assert_eq!(output("print true == 1;"), "false\n");
+        assert_eq!(output("print 0 / 0 == 0 / 0;"), "false\n");
+    }
+
+    #[test]
+    fn test_truthiness() {
+        assert_eq!(output("print !nil; print !false; print !0; print !\"\"; print !!true;"), "true\ntrue\nfalse\nfalse\ntrue\n");
+    }
+
+    #[test]
+    fn test_logical_operators_short_circuit() {
+        assert_eq!(output("print nil or \"default\";"), "default\n");
+        assert_eq!(output("print 1 or undefined;"), "1\n");
+        assert_eq!(output("print false and undefined;"), "false\n");
+        assert_eq!(runtime_error("print nil or undefined;").message, "Undefined variable 'undefined'.");
+        assert_eq!(output("print 1 and 2;"), "2\n");
+    }
+
+    #[test]
+    fn test_control_flow() {
+        assert_eq!(output("if (1 < 2) print \"yes\"; else print \"no\";"), "yes\n");
+        assert_eq!(output("if (nil) print \"yes\"; else print \"no\";"), "no\n");
+        assert_eq!(output("if (false) print 1;"), "");
+        assert_eq!(output("while (false) print 1; { print 2; print 3; }"), "2\n3\n");
+    }
+
+    #[test]
+    fn test_variables() {
+        assert_eq!(output("var a = 1; var b; print a; print b;"), "1\nnil\n");
+        assert_eq!(output("var a = 1; var a = \"again\"; print a;"), "again\n");
+        assert_eq!(output("var a; var b; a = b = 2; print a + b;"), "4\n");
+        assert_eq!(output("var a = 1; print a = 3; print a;"), "3\n3\n");
+    }
+
+    #[test]
+    fn test_block_scoping() {
+        let source = "
+            var a = \"global a\";
+            var b = \"global b\";
+            {
+                var a = \"outer a\";
+                {
+                    var a = \"inner a\";
+                    print a;
+                    print b;
+                    b = \"assigned b\";
+                }
+                print a;
+            }
+            print a;
+            print b;
+        ";
+        assert_eq!(output(source), "inner a\nglobal b\nouter a\nglobal a\nassigned b\n");
+    }
+
+    #[test]
+    fn test_global_initializer_sees_previous_definition() {
+        assert_eq!(output("var a = 1; var a = a + 1; print a;"), "2\n");
+    }
+
+    #[test]
+    fn test_closures_bind_to_declaration_scope() {
+        let source = "
+            var a = \"global\";
+            {
+                fun show() { print a; }
+                show();
+                var a = \"block\";
+                show();
+            }
+        ";
+        assert_eq!(output(source), "global\nglobal\n");
+    }
+
+    #[test]
+    fn test_block_variables_do_not_leak() {
+        let error = runtime_error("{ var inner = 1; }\nprint inner;");
+        assert_eq!((error.line, error.message.as_str()), (2, "Undefined variable 'inner'."));
+    }
+
+    #[test]
+    fn test_undefined_variable_errors() {
+        let error = runtime_error("print\nmissing;");
+        assert_eq!((error.line, error.message.as_str()), (2, "Undefined variable 'missing'."));
+
+        let error = runtime_error("\n\nmissing = 1;");
+        assert_eq!((error.line, error.message.as_str()), (3, "Undefined variable 'missing'."));
+    }
+
+    #[test]
+    fn test_scope_restored_after_error() {
+        let output = SharedOutput::default();
+        let mut interpreter = Interpreter::with_output(Box::new(output.clone()));
+        let parse = |source| {
+            let statements = Parser::new(Scanner::new(source).scan_tokens().0).parse().unwrap();
+            Resolver::new().resolve(&statements).unwrap();
+            statements
+        };
+
+        interpreter.interpret(&parse("var a = \"global\";")).unwrap();
+        assert!(interpreter.interpret(&parse("{ var a = \"local\"; print -a; }")).is_err());
+        interpreter.interpret(&parse("print a;")).unwrap();
+        assert_eq!(String::from_utf8(output.0.borrow().clone()).unwrap(), "global\n");
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(output("fun add(a, b) { return a + b; } print add(1, 2);"), "3\n");
+        assert_eq!(output("fun f() {} print f();"), "nil\n");
+        assert_eq!(output("fun f() { return; print \"unreachable\"; } print f();"), "nil\n");
+        assert_eq!(output("fun f() {} print f;"), "<fn f>\n");
+    }
+
+    #[test]
+    fn test_return_unwinds_loops_and_blocks() {
+        let source = "
+            fun first(limit) {
+                var i = 0;
+                while (true) {
+                    { if (i >= limit) return i; }
+                    i = i + 1;
+                }
+            }
+            print first(3);
+        ";
+        assert_eq!(output(source), "3\n");
+    }
+
+    #[test]
+    fn test_recursion() {
+        let source = "
+            fun fib(n) {
+                if (n < 2) return n;
+                return fib(n - 2) + fib(n - 1);
+            }
+            print fib(15);
+        ";
+        assert_eq!(output(source), "610\n");
+    }
+
+    #[test]
+    fn test_closures() {
+        let source = "
+            fun makeCounter() {
+                var i = 0;
+                fun count() {
+                    i = i + 1;
+                    return i;
+                }
+                return count;
+            }
+            var a = makeCounter();
+            var b = makeCounter();
+            print a();
+            print a();
+            print b();
+        ";
+        assert_eq!(output(source), "1\n2\n1\n");
+    }
+
+    #[test]
+    fn test_functions_as_arguments() {
+        let source = "
+            fun twice(f, x) { return f(f(x)); }
+            fun inc(n) { return n + 1; }
+            print twice(inc, 5);
+        ";
+        assert_eq!(output(source), "7\n");
+    }
+
+    #[test]
+    fn test_call_errors() {
+        let error = runtime_error("\"not a function\"(\n);");
+        assert_eq!((error.line, error.message.as_str()), (2, "Can only call functions and classes."));
+
+        let error = runtime_error("fun f(a, b) {}\nf(1,\n2,\n3);");
+        assert_eq!((error.line, error.message.as_str()), (4, "Expected 2 arguments but got 3."));
+    }
+
+    #[test]
+    fn test_unbounded_recursion_is_a_runtime_error() {
+        // 测试线程的栈只有 2 MB，和 main 一样在 STACK_SIZE 大小的线程上运行
+        let run_deep = |source: &'static str| {
+            std::thread::Builder::new()
+                .stack_size(STACK_SIZE)
+                .spawn(move || run(source))
+                .unwrap()
+                .join()
+                .unwrap()
+        };
+
+        let (text, error) = run_deep("fun c(n) { if (n == 0) return 0; return 1 + c(n - 1); }\nprint c(999);");
+        assert_eq!((text.as_str(), error), ("999\n", None));
+
+        let (_, error) = run_deep("fun f(n) {\n  return f(n + 1);\n}\nf(0);");
+        let error = error.expect("应该产生运行时错误");
+        assert_eq!((error.line, error.message.as_str()), (2, "Stack overflow."));
+        // 每一层调用一帧，再加上脚本本身
+        assert_eq!(error.trace.len(), MAX_CALL_DEPTH + 1);
+        assert_eq!(error.trace.last().map(|frame| frame.line), Some(4));
+    }
+
+    #[test]
+    fn test_classes_and_fields() {
+        assert_eq!(output("class Bagel {} print Bagel; print Bagel();"), "Bagel\nBagel instance\n");
+        assert_eq!(output("class Box {} var b = Box(); b.value = 1; b.value = b.value + 1; print b.value;"), "2\n");
+    }
+
+    #[test]
+    fn test_methods_bind_this() {
+        let source = "
+            class Person {
+                greet() { return \"hi \" + this.name; }
+            }
+            var p = Person();
+            p.name = \"ann\";
+            var greet = p.greet;
+            p.name = \"bob\";
+            print greet();
+            print p.greet;
+        ";
+        assert_eq!(output(source), "hi bob\n<fn greet>\n");
+    }
+
+    #[test]
+    fn test_fields_shadow_methods() {
+        let source = "
+            class A { m() { return \"method\"; } }
+            fun f() { return \"field\"; }
+            var a = A();
+            a.m = f;
+            print a.m();
+        ";
+        assert_eq!(output(source), "field\n");
+    }
+
+    #[test]
+    fn test_initializers() {
+        let source = "
+            class Point {
+                init(x, y) {
+                    this.x = x;
+                    this.y = y;
+                    if (x == 0) return;
+                    this.x = x * 10;
+                }
+            }
+            var p = Point(1, 2);
+            print p.x + p.y;
+            var origin = Point(0, 5);
+            print origin.x;
+            print origin.init(3, 4) == origin;
+            print origin.x;
+        ";
+        assert_eq!(output(source), "12\n0\ntrue\n30\n");
+        assert_eq!(
+            runtime_error("class P { init(a) {} }\nP();").message,
+            "Expected 1 arguments but got 0."
+        );
+    }
+
+    #[test]
+    fn test_inheritance_and_super() {
+        let source = "
+            class A {
+                method() { return \"A method\"; }
+                name() { return \"A\"; }
+            }
+            class B < A {
+                method() { return \"B then \" + super.method(); }
+            }
+            class C < B {}
+            print C().method();
+            print C().name();
+        ";
+        assert_eq!(output(source), "B then A method\nA\n");
+    }
+
+    #[test]
+    fn test_super_binds_this() {
+        let source = "
+            class A { describe() { return this.label; } }
+            class B < A {
+                init() { this.label = \"b\"; }
+                describe() { var f = super.describe; return \"<\" + f() + \">\"; }
+            }
+            print B().describe();
+        ";
+        assert_eq!(output(source), "<b>\n");
+    }
+
+    #[test]
+    fn test_class_errors() {
+        let error = runtime_error("var NotAClass = 1;\nclass A < NotAClass {}");
+        assert_eq!((error.line, error.message.as_str()), (2, "Superclass must be a class."));
+
+        let error = runtime_error("class A {}\nA().missing;");
+        assert_eq!((error.line, error.message.as_str()), (2, "Undefined property 'missing'."));
+
+        let error = runtime_error("class A {} class B < A { m() { return super.missing(); } } B().m();");
+        assert_eq!(error.message, "Undefined property 'missing'.");
+
+        assert_eq!(runtime_error("1.field;").message, "Only instances have properties.");
+        assert_eq!(runtime_error("\"s\".field = 1;").message, "Only instances have fields.");
+    }
+
+    // 辅助函数：用注册了本地函数的解释器运行程序
+    fn run_with_native(source: &str, native: NativeFunction) -> (String, Option<RuntimeError>) {
+        let statements = Parser::new(Scanner::new(source).scan_tokens().0).parse().unwrap();
+        Resolver::new().resolve(&statements).unwrap();
+
+        let output = SharedOutput::default();
+        let mut interpreter = Interpreter::with_output(Box::new(output.clone()));
+        let name = native.name().to_string();
+        interpreter.define_global(&name, Value::Callable(Rc::new(native)));
+        let error = interpreter.interpret(&statements).err();
+        let text = String::from_utf8(output.0.borrow().clone()).unwrap();
+        (text, error)
+    }
+
+    #[test]
+    fn test_native_functions() {
+        let add = NativeFunction::new("add", 2, |arguments| match arguments {
+            [Value::Number(a), Value::Number(b)] => Ok(Value::Number(a + b)),
+            _ => Err(RuntimeError::native("Arguments must be numbers.")),
+        });
+        let (text, error) = run_with_native("print add(1, 2); print add;", add);
+        assert_eq!(text, "3\n<native fn>\n");
+        assert_eq!(error, None);
+    }
+
+    #[test]
+    fn test_native_function_errors() {
+        let id = || NativeFunction::new("id", 1, |arguments| Ok(arguments[0].clone()));
+        let (_, error) = run_with_native("id(1,\n2);", id());
+        let error = error.unwrap();
+        assert_eq!((error.line, error.message.as_str()), (2, "Expected 1 arguments but got 2."));
+
+        let fail = NativeFunction::new("fail", 0, |_| Err(RuntimeError::native("Failed.")));
+        let (_, error) = run_with_native("\n\nfail(\n);", fail);
+        let error = error.unwrap();
+        assert_eq!((error.line, error.message.as_str()), (4, "Failed."));
+    }
+
+    #[test]
+    fn test_clock() {
+        let native = NativeFunction::new("clock", 0, clock);
+        let (text, error) = run_with_native("var t = clock(); print t > 0; print clock() >= t;", native);
+        assert_eq!(text, "true\ntrue\n");
+        assert_eq!(error, None);
+    }
+
+    #[test]
+    fn test_stack_trace() {
+        let source = "
+            fun inner() {
+                return 1 + nil;
+            }
+            fun outer() {
+                print \"calling\";
+                inner();
+            }
+            outer();
+        ";
+        let error = runtime_error(source);
+        let frame = |function: Option<&str>, line| StackFrame { function: function.map(str::to_string), line };
+        assert_eq!(
+            error.trace,
+            vec![frame(Some("inner"), 3), frame(Some("outer"), 7), frame(None, 9)]
+        );
+        assert_eq!(
+            error.to_string(),
+            "Operands must be two numbers or two strings.\n[line 3] in inner()\n[line 7] in outer()\n[line 9] in script"
+        );
+    }
+
+    #[test]
+    fn test_stack_trace_for_methods_and_natives() {
+        let source = "class A {\n  init() {\n    this.fail();\n  }\n  fail() { return -this; }\n}\nA();";
+        let trace: Vec<String> = runtime_error(source).trace.iter().map(|frame| frame.to_string()).collect();
+        assert_eq!(trace, ["[line 5] in fail()", "[line 3] in A()", "[line 7] in script"]);
+
+        let fail = NativeFunction::new("fail", 0, |_| Err(RuntimeError::native("Failed.")));
+        let (_, error) = run_with_native("fun f() {\n  fail();\n}\nf();", fail);
+        let trace: Vec<String> = error.unwrap().trace.iter().map(|frame| frame.to_string()).collect();
+        assert_eq!(trace, ["[line 2] in f()", "[line 4] in script"]);
+    }
+
+    #[test]
+    fn test_repeated_frames_are_collapsed() {
+        let source = "fun f(n) {\n  if (n == 0) return -nil;\n  return f(n - 1);\n}\nf(3);";
+        assert_eq!(
+            runtime_error(source).to_string(),
+            "Operand must be a number.\n[line 2] in f()\n[line 3] in f() (repeated 3 times)\n[line 5] in script"
+        );
+    }
+
+    #[test]
+    fn test_top_level_error_trace() {
+        let error = runtime_error("\n-nil;");
+        assert_eq!(error.to_string(), "Operand must be a number.\n[line 2] in script");
+    }
+
+    #[test]
+    fn test_operand_type_errors() {
+        let error = runtime_error("print -\"a\";");
+        assert_eq!(error.message, "Operand must be a number.");
+
+        let error = runtime_error("print 1 <\n\"a\";");
+        assert_eq!(error.message, "Operands must be numbers.");
+        assert_eq!(error.line, 1);
+
+        let error = runtime_error("print\n\n1 * nil;");
+        assert_eq!((error.line, error.message.as_str()), (3, "Operands must be numbers."));
+
+        let error = runtime_error("print \"a\" + 1;");
+        assert_eq!(error.message, "Operands must be two numbers or two strings.");
+    }
+
+    #[test]
+    fn test_runtime_error_stops_execution() {
+        let (text, error) = run("print 1; print -nil; print 2;");
+        assert_eq!(text, "1\n");
+        assert!(error.is_some());
+    }
+}
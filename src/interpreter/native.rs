@@ -0,0 +1,88 @@
+use std::{
+    fmt,
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{Callable, Interpreter, RuntimeError, Value};
+
+/// The Rust function behind a native function
+pub type NativeFn = dyn Fn(&[Value]) -> Result<Value, RuntimeError>;
+
+/// A function implemented in Rust and callable from Lox code
+/// 
+/// # Fields
+/// 
+/// * `name` - The global name the function is registered under
+/// * `arity` - The number of arguments the function expects
+/// * `function` - The Rust function to call
+pub struct NativeFunction {
+    name: String,
+    arity: usize,
+    function: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    /// Creates a native function
+    /// 
+    /// # Arguments
+    /// 
+    /// * `name` - The global name the function is registered under
+    /// * `arity` - The number of arguments the function expects
+    /// * `function` - The Rust function to call; it is only called with
+    ///   exactly `arity` arguments
+    pub fn new(
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value, RuntimeError> + 'static,
+    ) -> NativeFunction {
+        NativeFunction {
+            name: name.to_string(),
+            arity,
+            function: Box::new(function),
+        }
+    }
+
+    /// Returns the name the function is registered under
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Calls the Rust function
+    /// 
+    /// # Arguments
+    /// 
+    /// * `arguments` - The arguments, which the caller has checked against
+    ///   the arity
+    pub fn invoke(&self, arguments: &[Value]) -> Result<Value, RuntimeError> {
+        (self.function)(arguments)
+    }
+}
+
+impl Callable for NativeFunction {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn trace_name(&self) -> Option<&str> {
+        None
+    }
+
+    fn call(self: Rc<Self>, _interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        self.invoke(&arguments)
+    }
+}
+
+impl fmt::Display for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn>")
+    }
+}
+
+/// The built-in `clock()`: seconds since the Unix epoch
+pub fn clock(_arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| RuntimeError::native("System clock is before the Unix epoch."))?;
+    Ok(Value::Number(elapsed.as_secs_f64()))
+}
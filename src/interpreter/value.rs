@@ -0,0 +1,118 @@
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use super::{Class, Instance, Interpreter, RuntimeError};
+
+/// A runtime value in the Lox language
+/// 
+/// Strings are immutable and shared, so copying a value never copies
+/// string contents. Callables and instances are compared by identity.
+/// 
+/// # Variants
+/// 
+/// * `Nil` - The absence of a value
+/// * `Bool` - `true` or `false`
+/// * `Number` - A double-precision floating point number
+/// * `String` - An immutable string
+/// * `Callable` - Anything that can be called, such as a function
+/// * `Instance` - An instance of a class
+#[derive(Clone)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(Rc<str>),
+    Callable(Rc<dyn Callable>),
+    Instance(Rc<RefCell<Instance>>),
+}
+
+impl Value {
+    /// Checks whether the value counts as true in a condition
+    /// 
+    /// `nil` and `false` are falsey; every other value is truthy,
+    /// including `0` and the empty string.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    /// Returns the name of the value's type, for error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Callable(_) => "function",
+            Value::Instance(_) => "instance",
+        }
+    }
+}
+
+impl PartialEq for Value {
+    /// Lox equality: values of different types are never equal, numbers
+    /// follow IEEE 754, strings compare by contents and everything else
+    /// by identity
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Callable(a), Value::Callable(b)) => Rc::ptr_eq(a, b),
+            (Value::Instance(a), Value::Instance(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Callable(callable) => write!(f, "{}", callable),
+            Value::Instance(instance) => write!(f, "{} instance", instance.borrow().class.name),
+        }
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::String(s) => write!(f, "{:?}", s),
+            value => write!(f, "{}", value),
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value {
+        Value::String(Rc::from(s))
+    }
+}
+
+/// Something that can be called from Lox code
+/// 
+/// `Display` gives the text `print` shows for the callable.
+pub trait Callable: fmt::Display {
+    /// The number of arguments the callable expects
+    fn arity(&self) -> usize;
+
+    /// The name shown for calls to the callable in stack traces, or `None`
+    /// for native functions, which do not get a frame of their own
+    fn trace_name(&self) -> Option<&str>;
+
+    /// Calls the callable with arguments whose count matches `arity`
+    /// 
+    /// # Arguments
+    /// 
+    /// * `interpreter` - The interpreter making the call
+    /// * `arguments` - The evaluated arguments
+    fn call(self: Rc<Self>, interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, RuntimeError>;
+
+    /// Returns the callable as a class, if it is one
+    fn as_class(self: Rc<Self>) -> Option<Rc<Class>> {
+        None
+    }
+}
@@ -1,16 +1,119 @@
+//! A Lox interpreter, usable as a library
+//!
+//! Besides running programs through [`lox::Lox`], the crate exposes its
+//! lexer so other tools can tokenize Lox source directly:
+//!
+//! ```
+//! use rlox::{Scanner, TokenType};
+//!
+//! let kinds: Vec<TokenType> = Scanner::new("var answer = 42;")
+//!     .filter_map(Result::ok)
+//!     .map(|token| token.token_type)
+//!     .collect();
+//!
+//! assert_eq!(
+//!     kinds,
+//!     [TokenType::Var, TokenType::Identifier, TokenType::Equal, TokenType::Number, TokenType::Semicolon]
+//! );
+//! ```
+
+/// Syntax tree definitions
+pub mod ast;
+/// Tree-walking interpreter
+pub mod interpreter;
 /// Main module for the Lox interpreter
 pub mod lox;
+/// Parser module for syntax analysis
+pub mod parser;
+/// Printers that format syntax trees for debugging
+pub mod printer;
+/// Static analysis that binds variables to scopes
+pub mod resolver;
 /// Scanner module for lexical analysis
-mod scanner;
+pub mod scanner;
 /// Token module for lexical tokens
-mod token;
+pub mod token;
+/// Bytecode compiler and stack-based virtual machine
+pub mod vm;
+
+pub use parser::{ParseError, Parser};
+pub use resolver::{ResolveError, Resolver};
+pub use scanner::{ScanError, Scanner};
+pub use token::{Literal, OwnedToken, Span, Token, TokenType};
 
 /// Command-line argument structure for the Lox interpreter
 /// 
 /// # Fields
 /// 
 /// * `script` - Optional path to a Lox source file to execute
-#[derive(clap::Parser, Debug)]
+/// * `dump_tokens` - Print every token produced by the scanner
+/// * `dump_ast` - Print the syntax tree in the given format
+/// * `backend` - Which interpreter runs the program
+/// * `disassemble` - Print the bytecode of the program before running it
+/// * `trace_exec` - Print the value stack and each instruction as it runs
+/// * `stress_gc` - Collect garbage before every allocation
+/// * `log_gc` - Log allocations and garbage collections
+#[derive(clap::Parser, Debug, Default)]
 pub struct Args {
     pub script: Option<String>,
-}
\ No newline at end of file
+
+    /// Print every token produced by the scanner
+    #[arg(long)]
+    pub dump_tokens: bool,
+
+    /// Print the syntax tree, e.g. `--dump-ast=tree` (`sexpr` if no format is given)
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "sexpr"
+    )]
+    pub dump_ast: Option<AstFormat>,
+
+    /// Run programs with the given interpreter, e.g. `--backend=vm`
+    #[arg(long, value_enum, default_value_t = Backend::Tree)]
+    pub backend: Backend,
+
+    /// Print the bytecode of the program before running it (bytecode backend only)
+    #[arg(long)]
+    pub disassemble: bool,
+
+    /// Print the value stack before each instruction (bytecode backend only)
+    #[arg(long)]
+    pub trace_exec: bool,
+
+    /// Collect garbage before every allocation (bytecode backend only)
+    #[arg(long)]
+    pub stress_gc: bool,
+
+    /// Log allocations and garbage collections to stderr (bytecode backend only)
+    #[arg(long)]
+    pub log_gc: bool,
+}
+
+/// Output formats for `--dump-ast`
+/// 
+/// # Variants
+/// 
+/// * `Sexpr` - One parenthesized S-expression per statement
+/// * `Tree` - An indented tree, one node per line
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AstFormat {
+    Sexpr,
+    Tree,
+}
+
+/// Interpreters that can run a program, chosen with `--backend`
+/// 
+/// # Variants
+/// 
+/// * `Tree` - Walk the syntax tree after resolving it
+/// * `Vm` - Compile to bytecode and run it on a stack-based virtual machine
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    #[default]
+    Tree,
+    Vm,
+}
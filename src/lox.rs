@@ -1,12 +1,45 @@
-use std::{fs, io};
-use crate::{scanner::Scanner, Args};
+use std::{fs, io, rc::Rc};
+use crate::{
+    interpreter::{self, Interpreter, NativeFunction, RuntimeError, Value},
+    parser::{ParseError, Parser},
+    printer,
+    resolver::{ResolveError, Resolver},
+    scanner::{ScanError, Scanner},
+    token::{OwnedToken, TokenType},
+    vm::{CompileError, InterpretError, Vm},
+    Args, AstFormat, Backend,
+};
 
 /// The main interpreter for the Lox language
 /// 
 /// Manages the interpreter state and handles error reporting during execution.
 /// This struct serves as the primary entry point for executing Lox programs.
+/// 
+/// # Fields
+/// 
+/// * `had_error` - Whether a lexical, syntax or resolution error has been reported
+/// * `had_runtime_error` - Whether a runtime error has been reported
+/// * `interpreter` - The interpreter that executes parsed programs; its
+///   globals hold the registered native functions
+/// * `vm` - The virtual machine that runs compiled programs; it holds the
+///   same native functions
+/// * `backend` - Which of the two runs programs
+/// * `dump_tokens` - Whether to print every scanned token
+/// * `dump_ast` - The format to print syntax trees in, if any
 pub struct Lox {
     had_error: bool,
+    had_runtime_error: bool,
+    interpreter: Interpreter,
+    vm: Vm,
+    backend: Backend,
+    dump_tokens: bool,
+    dump_ast: Option<AstFormat>,
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Lox::new()
+    }
 }
 
 impl Lox {
@@ -17,8 +50,55 @@ impl Lox {
     /// # Returns
     /// 
     /// A new `Lox` instance with error tracking initialized to `false`
+    /// and the built-in native functions, such as `clock`, registered
     pub fn new() -> Lox {
-        Lox { had_error: false }
+        let mut lox = Lox {
+            had_error: false,
+            had_runtime_error: false,
+            interpreter: Interpreter::new(),
+            vm: Vm::new(),
+            backend: Backend::Tree,
+            dump_tokens: false,
+            dump_ast: None,
+        };
+        lox.register("clock", 0, interpreter::clock);
+        lox
+    }
+
+    /// Registers a native function that Lox programs can call
+    /// 
+    /// The function becomes a global variable. Calls with the wrong number
+    /// of arguments are runtime errors, so `function` always receives
+    /// exactly `arity` arguments. Registering a name again replaces the
+    /// previous definition.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `name` - The global name of the function
+    /// * `arity` - The number of arguments the function expects
+    /// * `function` - The Rust function to call; errors it returns are
+    ///   reported at the line of the call
+    /// 
+    /// # Examples
+    /// 
+    /// ```
+    /// use rlox::{interpreter::{RuntimeError, Value}, lox::Lox};
+    /// 
+    /// let mut lox = Lox::new();
+    /// lox.register("square", 1, |arguments| match arguments {
+    ///     [Value::Number(n)] => Ok(Value::Number(n * n)),
+    ///     _ => Err(RuntimeError::native("Argument must be a number.")),
+    /// });
+    /// ```
+    pub fn register(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value, RuntimeError> + 'static,
+    ) {
+        let native = Rc::new(NativeFunction::new(name, arity, function));
+        self.vm.define_native(Rc::clone(&native));
+        self.interpreter.define_global(name, Value::Callable(native));
     }
 
     /// Executes the Lox interpreter based on command-line arguments
@@ -35,7 +115,21 @@ impl Lox {
     /// 
     /// - If a script path is provided, executes the file
     /// - Otherwise, starts an interactive REPL session
+    /// - `--dump-tokens` and `--dump-ast` print the tokens and syntax tree
+    /// - `--backend` chooses between the tree-walking interpreter and the
+    ///   bytecode virtual machine
+    /// - `--disassemble` and `--trace-exec` list the bytecode and trace its
+    ///   execution on the virtual machine
+    /// - `--stress-gc` and `--log-gc` make its garbage collector run before
+    ///   every allocation and log what it does
     pub fn exec(&mut self, args: &Args) -> io::Result<()> {
+        self.backend = args.backend;
+        self.vm.set_disassemble(args.disassemble);
+        self.vm.set_trace_exec(args.trace_exec);
+        self.vm.set_stress_gc(args.stress_gc);
+        self.vm.set_log_gc(args.log_gc);
+        self.dump_tokens = args.dump_tokens;
+        self.dump_ast = args.dump_ast;
         match &args.script {
             Some(path) => self.run_file(path),
             None => self.run_prompt(),
@@ -56,7 +150,8 @@ impl Lox {
     /// 
     /// # Notes
     /// 
-    /// Exits with code 65 if any errors occur during execution
+    /// Exits with code 65 if a lexical, syntax or resolution error was reported, and
+    /// with code 70 if a runtime error occurred
     fn run_file(&mut self, path: &str) -> io::Result<()> {
         let content = fs::read_to_string(path)?;
         self.run(&content);
@@ -64,13 +159,17 @@ impl Lox {
         if self.had_error {
             std::process::exit(65);
         }
+        if self.had_runtime_error {
+            std::process::exit(70);
+        }
         Ok(())
     }
 
     /// Starts an interactive Read-Eval-Print Loop (REPL)
     /// 
     /// Provides a command-line interface for executing Lox code interactively.
-    /// Each line is executed independently, and errors don't affect subsequent lines.
+    /// Errors on one line don't affect subsequent lines, and global variables
+    /// defined on one line stay available to the following ones.
     /// 
     /// # Returns
     /// 
@@ -83,6 +182,7 @@ impl Lox {
             println!("> {}", line);
             self.run(&line);
             self.had_error = false;
+            self.had_runtime_error = false;
         }
 
         Ok(())
@@ -90,18 +190,87 @@ impl Lox {
 
     /// Executes a string of Lox source code
     /// 
-    /// Processes the source through the scanner and executes the resulting tokens.
-    /// Currently prints tokens for debugging purposes.
+    /// Processes the source through the scanner, the parser and the
+    /// resolver, then executes the program. Every lexical error is reported
+    /// before parsing starts, and nothing is parsed if there were any; a
+    /// program with resolution errors is not executed. When a syntax tree
+    /// dump is requested the tree is printed instead of being executed.
+    /// 
+    /// With the bytecode backend the source is compiled and run by the
+    /// virtual machine instead, unless a syntax tree dump is requested.
     /// 
     /// # Arguments
     /// 
     /// * `source` - The Lox source code to execute
     fn run(&mut self, source: &str) {
-        let mut scanner = Scanner::new(source.to_string());
-        scanner.scan_tokens();
+        if self.backend == Backend::Vm && self.dump_ast.is_none() {
+            self.run_vm(source);
+            return;
+        }
+
+        let mut scanner = Scanner::new(source);
+        let (tokens, errors) = scanner.scan_tokens();
+
+        for error in &errors {
+            self.scan_error(error);
+        }
+
+        if self.dump_tokens {
+            for token in &tokens {
+                println!("{:?}", token);
+            }
+        }
+        if self.had_error {
+            return;
+        }
+
+        match Parser::new(tokens).parse() {
+            Ok(statements) => match self.dump_ast {
+                Some(AstFormat::Sexpr) => print!("{}", printer::sexpr(&statements)),
+                Some(AstFormat::Tree) => print!("{}", printer::tree(&statements)),
+                None => {
+                    if let Err(errors) = Resolver::new().resolve(&statements) {
+                        for error in &errors {
+                            self.resolve_error(error);
+                        }
+                        return;
+                    }
+                    if let Err(error) = self.interpreter.interpret(&statements) {
+                        self.runtime_error(&error);
+                    }
+                }
+            },
+            Err(errors) => {
+                for error in &errors {
+                    self.parse_error(error);
+                }
+            }
+        }
+    }
+
+    /// Compiles and runs source code on the virtual machine
+    /// 
+    /// The compiler reads tokens straight from the scanner, so lexical,
+    /// syntax and resolution errors are all reported as compile errors.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `source` - The Lox source code to execute
+    fn run_vm(&mut self, source: &str) {
+        if self.dump_tokens {
+            for token in Scanner::new(source).filter_map(Result::ok) {
+                println!("{:?}", token);
+            }
+        }
 
-        for token in scanner.tokens {
-            println!("{:?}", token);
+        match self.vm.interpret(source) {
+            Ok(()) => (),
+            Err(InterpretError::Compile(errors)) => {
+                for error in &errors {
+                    self.compile_error(error);
+                }
+            }
+            Err(InterpretError::Runtime(error)) => self.runtime_error(&error),
         }
     }
 
@@ -111,18 +280,85 @@ impl Lox {
     /// 
     /// * `line` - The line number where the error occurred
     /// * `message` - A description of the error
-    pub fn error(line: usize, message: &str) {
+    pub fn error(&mut self, line: usize, message: &str) {
+        self.had_error = true;
         Lox::report(line, "", message);
     }
 
+    /// Reports a lexical error found by the scanner
+    /// 
+    /// The error is shown at the text that could not be scanned, or at the
+    /// end if the source ended inside it, as with an unterminated string.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `error` - The error, including its line and the offending text
+    fn scan_error(&mut self, error: &ScanError) {
+        self.had_error = true;
+        Lox::report(error.span.line, &error.location(), &error.message);
+    }
+
+    /// Reports a syntax error found by the parser
+    /// 
+    /// # Arguments
+    /// 
+    /// * `error` - The error, including the token where it was found
+    fn parse_error(&mut self, error: &ParseError) {
+        self.token_error(&error.token, &error.message);
+    }
+
+    /// Reports a semantic error found by the resolver
+    /// 
+    /// # Arguments
+    /// 
+    /// * `error` - The error, including the token where it was found
+    fn resolve_error(&mut self, error: &ResolveError) {
+        self.token_error(&error.token, &error.message);
+    }
+
+    /// Reports an error at a token, shown as `at 'lexeme'` or `at end`
+    /// 
+    /// # Arguments
+    /// 
+    /// * `token` - The token where the error was found
+    /// * `message` - A description of the error
+    fn token_error(&mut self, token: &OwnedToken, message: &str) {
+        self.had_error = true;
+        let location = match token.token_type {
+            TokenType::Eof => " at end".to_string(),
+            _ => format!(" at '{}'", token.lexeme),
+        };
+        Lox::report(token.line, &location, message);
+    }
+
+    /// Reports an error found by the bytecode compiler
+    /// 
+    /// # Arguments
+    /// 
+    /// * `error` - The error, including where it was found
+    fn compile_error(&mut self, error: &CompileError) {
+        self.had_error = true;
+        Lox::report(error.line, &error.location, &error.message);
+    }
+
+    /// Reports an error that stopped execution, followed by its stack trace
+    /// 
+    /// # Arguments
+    /// 
+    /// * `error` - The error, including the call stack when it occurred
+    fn runtime_error(&mut self, error: &RuntimeError) {
+        self.had_runtime_error = true;
+        eprintln!("{}", error);
+    }
+
     /// Formats and prints an error message to stderr
     /// 
     /// # Arguments
     /// 
     /// * `line` - The line number where the error occurred
-    /// * `position` - Additional position information (e.g., column or token)
+    /// * `location` - Where on the line the error is, e.g. ` at 'x'`, or empty
     /// * `message` - The error message to display
-    fn report(line: usize, position: &str, message: &str) {
-        eprintln!("[line {}] Error: {}: {}", line, position, message);
+    fn report(line: usize, location: &str, message: &str) {
+        eprintln!("[line {}] Error{}: {}", line, location, message);
     }
 }
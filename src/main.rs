@@ -1,6 +1,8 @@
 #![allow(unused)]
 
-use rlox::lox::Lox;
+use std::thread;
+
+use rlox::{interpreter::STACK_SIZE, lox::Lox};
 
 use clap::Parser;
 
@@ -8,8 +10,19 @@ use clap::Parser;
 /// 
 /// Parses command-line arguments and initializes the interpreter.
 /// Executes either a script file or starts a REPL session.
+/// 
+/// The session runs on its own thread, whose stack is large enough for
+/// the tree-walking interpreter's deepest recursion.
 fn main() {
     let args = rlox::Args::parse();
-    let mut lox = Lox::new();
-    lox.exec(&args).unwrap();
+    let session = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            let mut lox = Lox::new();
+            lox.exec(&args).unwrap();
+        })
+        .expect("failed to start the interpreter thread");
+    if session.join().is_err() {
+        std::process::exit(101);
+    }
 }
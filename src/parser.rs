@@ -0,0 +1,1004 @@
+use std::{error::Error, fmt, rc::Rc};
+
+use crate::{
+    ast::{Depth, Expr, Function, LiteralValue, Stmt},
+    token::{Literal, OwnedToken, Span, Token, TokenType},
+};
+
+/// The most arguments a call, or parameters a function, may have
+const MAX_ARITY: usize = 255;
+
+/// A syntax error found while parsing
+/// 
+/// # Fields
+/// 
+/// * `token` - The token where the error was detected
+/// * `message` - A description of the error
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub token: OwnedToken,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] {}", self.token.line, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Result type used inside the parser
+type ParseResult<T> = Result<T, ParseError>;
+
+/// Recursive-descent parser for the Lox language
+/// 
+/// Each grammar rule is a method; rules with lower precedence call the
+/// ones with higher precedence:
+/// 
+/// ```text
+/// program     → declaration* EOF ;
+/// declaration → classDecl | funDecl | varDecl | statement ;
+/// classDecl   → "class" IDENTIFIER ( "<" IDENTIFIER )? "{" function* "}" ;
+/// funDecl     → "fun" function ;
+/// function    → IDENTIFIER "(" parameters? ")" block ;
+/// varDecl     → "var" IDENTIFIER ( "=" expression )? ";" ;
+/// statement   → exprStmt | forStmt | ifStmt | printStmt | returnStmt
+///             | whileStmt | block ;
+/// forStmt     → "for" "(" ( varDecl | exprStmt | ";" ) expression? ";"
+///               expression? ")" statement ;
+/// ifStmt      → "if" "(" expression ")" statement ( "else" statement )? ;
+/// printStmt   → "print" expression ";" ;
+/// returnStmt  → "return" expression? ";" ;
+/// whileStmt   → "while" "(" expression ")" statement ;
+/// block       → "{" declaration* "}" ;
+/// exprStmt    → expression ";" ;
+/// 
+/// expression  → assignment ;
+/// assignment  → ( call "." )? IDENTIFIER "=" assignment | logic_or ;
+/// logic_or    → logic_and ( "or" logic_and )* ;
+/// logic_and   → equality ( "and" equality )* ;
+/// equality    → comparison ( ( "!=" | "==" ) comparison )* ;
+/// comparison  → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
+/// term        → factor ( ( "-" | "+" ) factor )* ;
+/// factor      → unary ( ( "/" | "*" ) unary )* ;
+/// unary       → ( "!" | "-" ) unary | call ;
+/// call        → primary ( "(" arguments? ")" | "." IDENTIFIER )* ;
+/// primary     → NUMBER | STRING | "true" | "false" | "nil" | "this"
+///             | IDENTIFIER | "(" expression ")" | "super" "." IDENTIFIER ;
+/// ```
+/// 
+/// When a declaration fails to parse, the parser records the error and
+/// skips ahead to the next statement boundary, so a single mistake does
+/// not hide the errors after it.
+/// 
+/// # Fields
+/// 
+/// * `tokens` - The tokens being parsed, always ending with `Eof`
+/// * `current` - Index of the next token to be consumed
+/// * `errors` - Syntax errors found so far
+pub struct Parser<'src> {
+    tokens: Vec<Token<'src>>,
+    current: usize,
+    errors: Vec<ParseError>,
+}
+
+impl<'src> Parser<'src> {
+    /// Creates a new Parser instance
+    /// 
+    /// Trivia such as doc comments are dropped, and an `Eof` token is
+    /// appended if the scanner did not produce one.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `tokens` - The tokens to be parsed
+    /// 
+    /// # Returns
+    /// 
+    /// A new Parser positioned at the first token
+    pub fn new(tokens: Vec<Token<'src>>) -> Parser<'src> {
+        let mut tokens: Vec<Token<'src>> = tokens
+            .into_iter()
+            .filter(|token| !token.token_type.is_trivia())
+            .collect();
+        if tokens.last().map(|token| token.token_type) != Some(TokenType::Eof) {
+            let span = tokens.last().map_or(Span::new(0, 0, 1, 1), end_of);
+            let line = tokens.last().map_or(1, |token| token.line);
+            tokens.push(Token::new(TokenType::Eof, "", line).with_span(span));
+        }
+        Parser { tokens, current: 0, errors: vec![] }
+    }
+
+    /// Parses a whole program
+    /// 
+    /// # Returns
+    /// 
+    /// The program's declarations, or every syntax error found in it
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<ParseError>> {
+        let mut statements = vec![];
+        while !self.is_at_end() {
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
+            }
+        }
+
+        if self.errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    /// Parses a single expression
+    /// 
+    /// # Returns
+    /// 
+    /// The expression, or the first syntax error found
+    pub fn parse_expression(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.expression()?;
+        if let Some(error) = self.errors.drain(..).next() {
+            return Err(error);
+        }
+        if !self.is_at_end() {
+            return Err(self.error(self.peek(), "Expect end of expression."));
+        }
+        Ok(expr)
+    }
+
+    /// declaration → classDecl | funDecl | varDecl | statement
+    ///
+    /// Returns `None` after recording an error and synchronizing.
+    fn declaration(&mut self) -> Option<Stmt> {
+        let result = if self.match_(&[TokenType::Class]) {
+            self.class_declaration()
+        } else if self.match_(&[TokenType::Fun]) {
+            self.function("function").map(Stmt::Function)
+        } else if self.match_(&[TokenType::Var]) {
+            self.var_declaration()
+        } else {
+            self.statement()
+        };
+
+        match result {
+            Ok(statement) => Some(statement),
+            Err(error) => {
+                self.errors.push(error);
+                self.synchronize();
+                None
+            }
+        }
+    }
+
+    /// classDecl → "class" IDENTIFIER ( "<" IDENTIFIER )? "{" function* "}"
+    fn class_declaration(&mut self) -> ParseResult<Stmt> {
+        let name = self.consume(TokenType::Identifier, "Expect class name.")?.to_owned_token();
+
+        let superclass = if self.match_(&[TokenType::Less]) {
+            let name = self.consume(TokenType::Identifier, "Expect superclass name.")?;
+            Some(Expr::Variable { name: name.to_owned_token(), depth: Depth::default() })
+        } else {
+            None
+        };
+
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
+        let mut methods = vec![];
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            methods.push(self.function("method")?);
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after class body.")?;
+
+        Ok(Stmt::Class { name, superclass, methods })
+    }
+
+    /// function → IDENTIFIER "(" parameters? ")" block
+    fn function(&mut self, kind: &str) -> ParseResult<Rc<Function>> {
+        let name = self
+            .consume(TokenType::Identifier, &format!("Expect {} name.", kind))?
+            .to_owned_token();
+        self.consume(TokenType::LeftParen, &format!("Expect '(' after {} name.", kind))?;
+
+        let mut params = vec![];
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if params.len() >= MAX_ARITY {
+                    let error = self.error(self.peek(), "Can't have more than 255 parameters.");
+                    self.errors.push(error);
+                }
+                params.push(self.consume(TokenType::Identifier, "Expect parameter name.")?.to_owned_token());
+                if !self.match_(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
+
+        self.consume(TokenType::LeftBrace, &format!("Expect '{{' before {} body.", kind))?;
+        let body = self.block()?;
+        Ok(Rc::new(Function { name, params, body }))
+    }
+
+    /// varDecl → "var" IDENTIFIER ( "=" expression )? ";"
+    fn var_declaration(&mut self) -> ParseResult<Stmt> {
+        let name = self.consume(TokenType::Identifier, "Expect variable name.")?.to_owned_token();
+
+        let initializer = if self.match_(&[TokenType::Equal]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        self.consume(TokenType::Semicolon, "Expect ';' after variable declaration.")?;
+        Ok(Stmt::Var { name, initializer })
+    }
+
+    /// statement → exprStmt | forStmt | ifStmt | printStmt | returnStmt | whileStmt | block
+    fn statement(&mut self) -> ParseResult<Stmt> {
+        if self.match_(&[TokenType::For]) {
+            return self.for_statement();
+        }
+        if self.match_(&[TokenType::If]) {
+            return self.if_statement();
+        }
+        if self.match_(&[TokenType::Print]) {
+            return self.print_statement();
+        }
+        if self.match_(&[TokenType::Return]) {
+            return self.return_statement();
+        }
+        if self.match_(&[TokenType::While]) {
+            return self.while_statement();
+        }
+        if self.match_(&[TokenType::LeftBrace]) {
+            return Ok(Stmt::Block { statements: self.block()? });
+        }
+        self.expression_statement()
+    }
+
+    /// forStmt → "for" "(" ( varDecl | exprStmt | ";" ) expression? ";" expression? ")" statement
+    ///
+    /// The loop is desugared into a block holding the initializer and a
+    /// `while` loop whose body runs the increment after the original body.
+    fn for_statement(&mut self) -> ParseResult<Stmt> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
+
+        let initializer = if self.match_(&[TokenType::Semicolon]) {
+            None
+        } else if self.match_(&[TokenType::Var]) {
+            Some(self.var_declaration()?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+
+        let condition = if self.check(TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::Semicolon, "Expect ';' after loop condition.")?;
+
+        let increment = if self.check(TokenType::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
+
+        let mut body = self.statement()?;
+        if let Some(increment) = increment {
+            body = Stmt::Block {
+                statements: vec![body, Stmt::Expression { expression: increment }],
+            };
+        }
+        let condition = condition.unwrap_or(Expr::Literal { value: LiteralValue::Bool(true) });
+        body = Stmt::While { condition, body: Box::new(body) };
+        if let Some(initializer) = initializer {
+            body = Stmt::Block { statements: vec![initializer, body] };
+        }
+        Ok(body)
+    }
+
+    /// ifStmt → "if" "(" expression ")" statement ( "else" statement )?
+    fn if_statement(&mut self) -> ParseResult<Stmt> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after if condition.")?;
+
+        let then_branch = Box::new(self.statement()?);
+        let else_branch = if self.match_(&[TokenType::Else]) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+        Ok(Stmt::If { condition, then_branch, else_branch })
+    }
+
+    /// printStmt → "print" expression ";"
+    fn print_statement(&mut self) -> ParseResult<Stmt> {
+        let expression = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
+        Ok(Stmt::Print { expression })
+    }
+
+    /// returnStmt → "return" expression? ";"
+    fn return_statement(&mut self) -> ParseResult<Stmt> {
+        let keyword = self.previous().to_owned_token();
+        let value = if self.check(TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::Semicolon, "Expect ';' after return value.")?;
+        Ok(Stmt::Return { keyword, value })
+    }
+
+    /// whileStmt → "while" "(" expression ")" statement
+    fn while_statement(&mut self) -> ParseResult<Stmt> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after condition.")?;
+        let body = Box::new(self.statement()?);
+        Ok(Stmt::While { condition, body })
+    }
+
+    /// block → "{" declaration* "}"
+    ///
+    /// The opening brace has already been consumed.
+    fn block(&mut self) -> ParseResult<Vec<Stmt>> {
+        let mut statements = vec![];
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after block.")?;
+        Ok(statements)
+    }
+
+    /// exprStmt → expression ";"
+    fn expression_statement(&mut self) -> ParseResult<Stmt> {
+        let expression = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after expression.")?;
+        Ok(Stmt::Expression { expression })
+    }
+
+    /// expression → assignment
+    fn expression(&mut self) -> ParseResult<Expr> {
+        self.assignment()
+    }
+
+    /// assignment → ( call "." )? IDENTIFIER "=" assignment | logic_or
+    ///
+    /// The target is parsed as an ordinary expression first; an invalid
+    /// target is recorded as an error without unwinding the parse.
+    fn assignment(&mut self) -> ParseResult<Expr> {
+        let expr = self.or()?;
+
+        if self.match_(&[TokenType::Equal]) {
+            let equals = self.previous().to_owned_token();
+            let value = Box::new(self.assignment()?);
+            return Ok(match expr {
+                Expr::Variable { name, depth } => Expr::Assign { name, value, depth },
+                Expr::Get { object, name } => Expr::Set { object, name, value },
+                expr => {
+                    self.errors.push(ParseError {
+                        token: equals,
+                        message: "Invalid assignment target.".to_string(),
+                    });
+                    expr
+                }
+            });
+        }
+        Ok(expr)
+    }
+
+    /// logic_or → logic_and ( "or" logic_and )*
+    fn or(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.and()?;
+        while self.match_(&[TokenType::Or]) {
+            let operator = self.previous().to_owned_token();
+            let right = self.and()?;
+            expr = Expr::Logical { left: Box::new(expr), operator, right: Box::new(right) };
+        }
+        Ok(expr)
+    }
+
+    /// logic_and → equality ( "and" equality )*
+    fn and(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.equality()?;
+        while self.match_(&[TokenType::And]) {
+            let operator = self.previous().to_owned_token();
+            let right = self.equality()?;
+            expr = Expr::Logical { left: Box::new(expr), operator, right: Box::new(right) };
+        }
+        Ok(expr)
+    }
+
+    /// equality → comparison ( ( "!=" | "==" ) comparison )*
+    fn equality(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.comparison()?;
+        while self.match_(&[TokenType::BangEqual, TokenType::EqualEqual]) {
+            let operator = self.previous().to_owned_token();
+            let right = self.comparison()?;
+            expr = Expr::Binary { left: Box::new(expr), operator, right: Box::new(right) };
+        }
+        Ok(expr)
+    }
+
+    /// comparison → term ( ( ">" | ">=" | "<" | "<=" ) term )*
+    fn comparison(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.term()?;
+        while self.match_(&[
+            TokenType::Greater,
+            TokenType::GreaterEqual,
+            TokenType::Less,
+            TokenType::LessEqual,
+        ]) {
+            let operator = self.previous().to_owned_token();
+            let right = self.term()?;
+            expr = Expr::Binary { left: Box::new(expr), operator, right: Box::new(right) };
+        }
+        Ok(expr)
+    }
+
+    /// term → factor ( ( "-" | "+" ) factor )*
+    fn term(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.factor()?;
+        while self.match_(&[TokenType::Minus, TokenType::Plus]) {
+            let operator = self.previous().to_owned_token();
+            let right = self.factor()?;
+            expr = Expr::Binary { left: Box::new(expr), operator, right: Box::new(right) };
+        }
+        Ok(expr)
+    }
+
+    /// factor → unary ( ( "/" | "*" ) unary )*
+    fn factor(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.unary()?;
+        while self.match_(&[TokenType::Slash, TokenType::Star]) {
+            let operator = self.previous().to_owned_token();
+            let right = self.unary()?;
+            expr = Expr::Binary { left: Box::new(expr), operator, right: Box::new(right) };
+        }
+        Ok(expr)
+    }
+
+    /// unary → ( "!" | "-" ) unary | call
+    fn unary(&mut self) -> ParseResult<Expr> {
+        if self.match_(&[TokenType::Bang, TokenType::Minus]) {
+            let operator = self.previous().to_owned_token();
+            let right = self.unary()?;
+            return Ok(Expr::Unary { operator, right: Box::new(right) });
+        }
+        self.call()
+    }
+
+    /// call → primary ( "(" arguments? ")" | "." IDENTIFIER )*
+    fn call(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.match_(&[TokenType::LeftParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.match_(&[TokenType::Dot]) {
+                let name = self.consume(TokenType::Identifier, "Expect property name after '.'.")?;
+                expr = Expr::Get { object: Box::new(expr), name: name.to_owned_token() };
+            } else {
+                break;
+            }
+        }
+        Ok(expr)
+    }
+
+    /// arguments → expression ( "," expression )*
+    ///
+    /// The opening parenthesis has already been consumed.
+    fn finish_call(&mut self, callee: Expr) -> ParseResult<Expr> {
+        let mut arguments = vec![];
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if arguments.len() >= MAX_ARITY {
+                    let error = self.error(self.peek(), "Can't have more than 255 arguments.");
+                    self.errors.push(error);
+                }
+                arguments.push(self.expression()?);
+                if !self.match_(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.")?.to_owned_token();
+        Ok(Expr::Call { callee: Box::new(callee), paren, arguments })
+    }
+
+    /// primary → NUMBER | STRING | "true" | "false" | "nil" | "this"
+    ///         | IDENTIFIER | "(" expression ")" | "super" "." IDENTIFIER
+    fn primary(&mut self) -> ParseResult<Expr> {
+        let value = match self.peek().token_type {
+            TokenType::False => LiteralValue::Bool(false),
+            TokenType::True => LiteralValue::Bool(true),
+            TokenType::Nil => LiteralValue::Nil,
+            TokenType::Number | TokenType::String => match &self.peek().literal {
+                Some(Literal::Number(n)) => LiteralValue::Number(*n),
+                Some(Literal::String(s)) => LiteralValue::String(s.clone()),
+                None => return Err(self.error(self.peek(), "Expect expression.")),
+            },
+            TokenType::This => {
+                let keyword = self.advance().to_owned_token();
+                return Ok(Expr::This { keyword, depth: Depth::default() });
+            }
+            TokenType::Identifier => {
+                let name = self.advance().to_owned_token();
+                return Ok(Expr::Variable { name, depth: Depth::default() });
+            }
+            TokenType::Super => {
+                let keyword = self.advance().to_owned_token();
+                self.consume(TokenType::Dot, "Expect '.' after 'super'.")?;
+                let method = self.consume(TokenType::Identifier, "Expect superclass method name.")?;
+                return Ok(Expr::Super { keyword, method: method.to_owned_token(), depth: Depth::default() });
+            }
+            TokenType::LeftParen => {
+                self.advance();
+                let expression = self.expression()?;
+                self.consume(TokenType::RightParen, "Expect ')' after expression.")?;
+                return Ok(Expr::Grouping { expression: Box::new(expression) });
+            }
+            _ => return Err(self.error(self.peek(), "Expect expression.")),
+        };
+        self.advance();
+        Ok(Expr::Literal { value })
+    }
+
+    /// discard tokens until the start of the next statement
+    ///
+    /// Called after a syntax error so that parsing can resume at a point
+    /// where the following tokens are likely to make sense again.
+    fn synchronize(&mut self) {
+        self.advance();
+        while !self.is_at_end() {
+            if self.previous().token_type == TokenType::Semicolon {
+                return;
+            }
+            match self.peek().token_type {
+                TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return => return,
+                _ => {
+                    self.advance();
+                }
+            }
+        }
+    }
+
+    /// consume the next token if it has any of the given types
+    fn match_(&mut self, types: &[TokenType]) -> bool {
+        if types.iter().any(|&token_type| self.check(token_type)) {
+            self.advance();
+            return true;
+        }
+        false
+    }
+
+    /// consume the next token, which must have the given type
+    fn consume(&mut self, token_type: TokenType, message: &str) -> ParseResult<&Token<'src>> {
+        if self.check(token_type) {
+            return Ok(self.advance());
+        }
+        Err(self.error(self.peek(), message))
+    }
+
+    /// check if the next token has the given type
+    fn check(&self, token_type: TokenType) -> bool {
+        !self.is_at_end() && self.peek().token_type == token_type
+    }
+
+    /// consume the next token and return it
+    fn advance(&mut self) -> &Token<'src> {
+        if !self.is_at_end() {
+            self.current += 1;
+        }
+        self.previous()
+    }
+
+    /// check if we have reached the `Eof` token
+    fn is_at_end(&self) -> bool {
+        self.peek().token_type == TokenType::Eof
+    }
+
+    /// the next token to be consumed
+    fn peek(&self) -> &Token<'src> {
+        &self.tokens[self.current]
+    }
+
+    /// the most recently consumed token
+    fn previous(&self) -> &Token<'src> {
+        &self.tokens[self.current - 1]
+    }
+
+    /// build an error at the given token
+    fn error(&self, token: &Token, message: &str) -> ParseError {
+        ParseError {
+            token: token.to_owned_token(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// the empty span just past the end of a token
+fn end_of(token: &Token) -> Span {
+    let column = match token.lexeme.rfind('\n') {
+        Some(newline) => token.lexeme[newline + 1..].chars().count() + 1,
+        None => token.span.column + token.lexeme.chars().count(),
+    };
+    Span::new(token.span.end, token.span.end, token.line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{printer::expr_sexpr as sexpr, scanner::Scanner};
+
+    // 辅助函数：解析单个表达式
+    fn parse(source: &str) -> Result<Expr, ParseError> {
+        let (tokens, errors) = Scanner::new(source).scan_tokens();
+        assert!(errors.is_empty(), "测试源代码不应该有词法错误");
+        Parser::new(tokens).parse_expression()
+    }
+
+    // 辅助函数：解析整个程序
+    fn parse_program(source: &str) -> Result<Vec<Stmt>, Vec<ParseError>> {
+        let (tokens, errors) = Scanner::new(source).scan_tokens();
+        assert!(errors.is_empty(), "测试源代码不应该有词法错误");
+        Parser::new(tokens).parse()
+    }
+
+    // 辅助函数：解析程序并返回所有错误信息 (行号, 消息)
+    fn parse_errors(source: &str) -> Vec<(usize, String)> {
+        parse_program(source)
+            .unwrap_err()
+            .into_iter()
+            .map(|e| (e.token.line, e.message))
+            .collect()
+    }
+
+    fn parse_sexpr(source: &str) -> String {
+        sexpr(&parse(source).unwrap())
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eq!(parse_sexpr("123"), "123");
+        assert_eq!(parse_sexpr("4.5"), "4.5");
+        assert_eq!(parse_sexpr("\"hi\""), "\"hi\"");
+        assert_eq!(parse_sexpr("true"), "true");
+        assert_eq!(parse_sexpr("false"), "false");
+        assert_eq!(parse_sexpr("nil"), "nil");
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(parse_sexpr("1 + 2 * 3"), "(+ 1 (* 2 3))");
+        assert_eq!(parse_sexpr("1 * 2 + 3"), "(+ (* 1 2) 3)");
+        assert_eq!(parse_sexpr("1 + 2 < 3 * 4"), "(< (+ 1 2) (* 3 4))");
+        assert_eq!(parse_sexpr("1 < 2 == 3 >= 4"), "(== (< 1 2) (>= 3 4))");
+        assert_eq!(parse_sexpr("-1 * !true"), "(* (- 1) (! true))");
+    }
+
+    #[test]
+    fn test_left_associativity() {
+        assert_eq!(parse_sexpr("1 - 2 - 3"), "(- (- 1 2) 3)");
+        assert_eq!(parse_sexpr("8 / 4 / 2"), "(/ (/ 8 4) 2)");
+        assert_eq!(parse_sexpr("1 == 2 != 3"), "(!= (== 1 2) 3)");
+    }
+
+    #[test]
+    fn test_nested_unary() {
+        assert_eq!(parse_sexpr("!!true"), "(! (! true))");
+        assert_eq!(parse_sexpr("--1"), "(- (- 1))");
+    }
+
+    #[test]
+    fn test_grouping() {
+        assert_eq!(parse_sexpr("-123 * (45.67)"), "(* (- 123) (group 45.67))");
+        assert_eq!(parse_sexpr("(1 + 2) * 3"), "(* (group (+ 1 2)) 3)");
+        assert_eq!(parse_sexpr("((nil))"), "(group (group nil))");
+    }
+
+    #[test]
+    fn test_doc_comments_are_skipped() {
+        assert_eq!(parse_sexpr("/// docs\n1 + /// more\n2"), "(+ 1 2)");
+    }
+
+    #[test]
+    fn test_operator_token_keeps_line() {
+        let expr = parse("1\n+\n2").unwrap();
+        match expr {
+            Expr::Binary { operator, .. } => {
+                assert_eq!(operator.token_type, TokenType::Plus);
+                assert_eq!(operator.line, 2);
+            }
+            _ => panic!("应该是二元表达式"),
+        }
+    }
+
+    #[test]
+    fn test_missing_expression() {
+        let error = parse("1 +").unwrap_err();
+        assert_eq!(error.message, "Expect expression.");
+        assert_eq!(error.token.token_type, TokenType::Eof);
+        assert_eq!(error.token.line, 1);
+    }
+
+    #[test]
+    fn test_unclosed_grouping() {
+        let error = parse("(1 + 2\n").unwrap_err();
+        assert_eq!(error.message, "Expect ')' after expression.");
+        assert_eq!(error.token.token_type, TokenType::Eof);
+    }
+
+    #[test]
+    fn test_error_reports_offending_token() {
+        let error = parse("1 +\n* 2").unwrap_err();
+        assert_eq!(error.message, "Expect expression.");
+        assert_eq!(error.token.lexeme, "*");
+        assert_eq!(error.token.line, 2);
+    }
+
+    #[test]
+    fn test_trailing_tokens() {
+        let error = parse("1 2").unwrap_err();
+        assert_eq!(error.message, "Expect end of expression.");
+        assert_eq!(error.token.lexeme, "2");
+    }
+
+    #[test]
+    fn test_eof_span_follows_last_token() {
+        let error = parse("(1 +\n  \"a\nbc\"").unwrap_err();
+        assert_eq!(error.token.token_type, TokenType::Eof);
+        assert_eq!(error.token.span, Span::new(13, 13, 3, 4));
+    }
+
+    #[test]
+    fn test_empty_input() {
+        let error = parse("").unwrap_err();
+        assert_eq!(error.message, "Expect expression.");
+        assert_eq!(error.token.token_type, TokenType::Eof);
+    }
+
+    #[test]
+    fn test_assignment_is_right_associative() {
+        assert_eq!(parse_sexpr("a = b = 1"), "(= a (= b 1))");
+        assert_eq!(parse_sexpr("a.b.c = d"), "(= (. (. a b) c) d)");
+    }
+
+    #[test]
+    fn test_invalid_assignment_target() {
+        let error = parse("a + b = c").unwrap_err();
+        assert_eq!(error.message, "Invalid assignment target.");
+        assert_eq!(error.token.lexeme, "=");
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        assert_eq!(parse_sexpr("a or b and c"), "(or a (and b c))");
+        assert_eq!(parse_sexpr("a == b and c or d"), "(or (and (== a b) c) d)");
+    }
+
+    #[test]
+    fn test_calls_and_properties() {
+        assert_eq!(parse_sexpr("f()"), "(call f)");
+        assert_eq!(parse_sexpr("f(1, a + b)(2)"), "(call (call f 1 (+ a b)) 2)");
+        assert_eq!(parse_sexpr("a.b(c).d"), "(. (call (. a b) c) d)");
+        assert_eq!(parse_sexpr("-a.b"), "(- (. a b))");
+        assert_eq!(parse_sexpr("this.x"), "(. this x)");
+        assert_eq!(parse_sexpr("super.init(1)"), "(call (super init) 1)");
+    }
+
+    #[test]
+    fn test_call_paren_token() {
+        match parse("f(\n1\n)").unwrap() {
+            Expr::Call { paren, .. } => assert_eq!(paren.line, 3),
+            _ => panic!("应该是函数调用"),
+        }
+    }
+
+    #[test]
+    fn test_too_many_arguments() {
+        let arguments = vec!["1"; 256].join(", ");
+        let errors = parse_errors(&format!("f({});", arguments));
+        assert_eq!(errors, vec![(1, "Can't have more than 255 arguments.".to_string())]);
+
+        let arguments = vec!["1"; 255].join(", ");
+        assert!(parse_program(&format!("f({});", arguments)).is_ok());
+    }
+
+    #[test]
+    fn test_too_many_parameters() {
+        let params: Vec<String> = (0..256).map(|i| format!("p{}", i)).collect();
+        let errors = parse_errors(&format!("fun f({}) {{}}", params.join(", ")));
+        assert_eq!(errors, vec![(1, "Can't have more than 255 parameters.".to_string())]);
+    }
+
+    #[test]
+    fn test_print_and_expression_statements() {
+        let statements = parse_program("print 1 + 2;\nf();").unwrap();
+        assert_eq!(statements.len(), 2);
+        assert!(matches!(&statements[0], Stmt::Print { expression } if sexpr(expression) == "(+ 1 2)"));
+        assert!(matches!(&statements[1], Stmt::Expression { expression } if sexpr(expression) == "(call f)"));
+    }
+
+    #[test]
+    fn test_var_declaration() {
+        let statements = parse_program("var a = 1; var b;").unwrap();
+        match &statements[..] {
+            [Stmt::Var { name: a, initializer: Some(init) }, Stmt::Var { name: b, initializer: None }] => {
+                assert_eq!(a.lexeme, "a");
+                assert_eq!(sexpr(init), "1");
+                assert_eq!(b.lexeme, "b");
+            }
+            _ => panic!("unexpected statements: {:?}", statements),
+        }
+    }
+
+    #[test]
+    fn test_block_statement() {
+        let statements = parse_program("{ var a = 1; { print a; } }").unwrap();
+        match &statements[..] {
+            [Stmt::Block { statements }] => {
+                assert!(matches!(statements[0], Stmt::Var { .. }));
+                assert!(matches!(&statements[1], Stmt::Block { statements } if statements.len() == 1));
+            }
+            _ => panic!("unexpected statements: {:?}", statements),
+        }
+    }
+
+    #[test]
+    fn test_if_else_binds_to_nearest_if() {
+        let statements = parse_program("if (a) if (b) print 1; else print 2;").unwrap();
+        match &statements[..] {
+            [Stmt::If { else_branch: None, then_branch, .. }] => {
+                assert!(matches!(**then_branch, Stmt::If { else_branch: Some(_), .. }));
+            }
+            _ => panic!("unexpected statements: {:?}", statements),
+        }
+    }
+
+    #[test]
+    fn test_while_statement() {
+        let statements = parse_program("while (i < 10) i = i + 1;").unwrap();
+        match &statements[..] {
+            [Stmt::While { condition, body }] => {
+                assert_eq!(sexpr(condition), "(< i 10)");
+                assert!(matches!(**body, Stmt::Expression { .. }));
+            }
+            _ => panic!("unexpected statements: {:?}", statements),
+        }
+    }
+
+    #[test]
+    fn test_for_is_desugared_to_while() {
+        let statements = parse_program("for (var i = 0; i < 3; i = i + 1) print i;").unwrap();
+        match &statements[..] {
+            [Stmt::Block { statements }] => match &statements[..] {
+                [Stmt::Var { .. }, Stmt::While { condition, body }] => {
+                    assert_eq!(sexpr(condition), "(< i 3)");
+                    match &**body {
+                        Stmt::Block { statements } => {
+                            assert!(matches!(statements[0], Stmt::Print { .. }));
+                            assert!(matches!(&statements[1], Stmt::Expression { expression } if sexpr(expression) == "(= i (+ i 1))"));
+                        }
+                        other => panic!("unexpected body: {:?}", other),
+                    }
+                }
+                _ => panic!("unexpected statements: {:?}", statements),
+            },
+            _ => panic!("unexpected statements: {:?}", statements),
+        }
+    }
+
+    #[test]
+    fn test_empty_for_clauses() {
+        let statements = parse_program("for (;;) print 1;").unwrap();
+        match &statements[..] {
+            [Stmt::While { condition, body }] => {
+                assert_eq!(sexpr(condition), "true");
+                assert!(matches!(**body, Stmt::Print { .. }));
+            }
+            _ => panic!("unexpected statements: {:?}", statements),
+        }
+    }
+
+    #[test]
+    fn test_function_declaration() {
+        let statements = parse_program("fun add(a, b) { return a + b; }").unwrap();
+        match &statements[..] {
+            [Stmt::Function(function)] => {
+                assert_eq!(function.name.lexeme, "add");
+                let params: Vec<&str> = function.params.iter().map(|p| p.lexeme.as_str()).collect();
+                assert_eq!(params, vec!["a", "b"]);
+                assert!(matches!(&function.body[..], [Stmt::Return { value: Some(_), .. }]));
+            }
+            _ => panic!("unexpected statements: {:?}", statements),
+        }
+    }
+
+    #[test]
+    fn test_bare_return() {
+        let statements = parse_program("fun f() { return; }").unwrap();
+        match &statements[..] {
+            [Stmt::Function(function)] => {
+                assert!(matches!(&function.body[..], [Stmt::Return { value: None, .. }]));
+            }
+            _ => panic!("unexpected statements: {:?}", statements),
+        }
+    }
+
+    #[test]
+    fn test_class_declaration() {
+        let source = "class B < A { init(x) { this.x = x; } get() { return super.get(); } }";
+        let statements = parse_program(source).unwrap();
+        match &statements[..] {
+            [Stmt::Class { name, superclass: Some(Expr::Variable { name: superclass, .. }), methods }] => {
+                assert_eq!(name.lexeme, "B");
+                assert_eq!(superclass.lexeme, "A");
+                let names: Vec<&str> = methods.iter().map(|m| m.name.lexeme.as_str()).collect();
+                assert_eq!(names, vec!["init", "get"]);
+            }
+            _ => panic!("unexpected statements: {:?}", statements),
+        }
+    }
+
+    #[test]
+    fn test_statement_errors() {
+        assert_eq!(parse_errors("print 1"), vec![(1, "Expect ';' after value.".to_string())]);
+        assert_eq!(parse_errors("1 + 2"), vec![(1, "Expect ';' after expression.".to_string())]);
+        assert_eq!(parse_errors("var 1;"), vec![(1, "Expect variable name.".to_string())]);
+        assert_eq!(parse_errors("{ print 1;"), vec![(1, "Expect '}' after block.".to_string())]);
+        assert_eq!(parse_errors("if a"), vec![(1, "Expect '(' after 'if'.".to_string())]);
+        assert_eq!(parse_errors("fun (a) {}"), vec![(1, "Expect function name.".to_string())]);
+        assert_eq!(parse_errors("class A { 1 }"), vec![(1, "Expect method name.".to_string())]);
+        assert_eq!(parse_errors("class A < { }"), vec![(1, "Expect superclass name.".to_string())]);
+        assert_eq!(parse_errors("super;"), vec![(1, "Expect '.' after 'super'.".to_string())]);
+    }
+
+    #[test]
+    fn test_synchronize_reports_every_error() {
+        let source = "var = 1;\nprint 2;\nprint (3;\nvar ok = 4;\nfun 5() {}\nwhile x) {}";
+        assert_eq!(
+            parse_errors(source),
+            vec![
+                (1, "Expect variable name.".to_string()),
+                (3, "Expect ')' after expression.".to_string()),
+                (5, "Expect function name.".to_string()),
+                (6, "Expect '(' after 'while'.".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_synchronize_at_keyword_without_semicolon() {
+        let source = "print 1 +\nvar a = 2;\nprint a b;\nprint 3;";
+        assert_eq!(
+            parse_errors(source),
+            vec![
+                (2, "Expect expression.".to_string()),
+                (3, "Expect ';' after value.".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_inside_block_does_not_hide_later_errors() {
+        let source = "{\n  var a = ;\n  print a;\n}\nprint ;";
+        assert_eq!(
+            parse_errors(source),
+            vec![
+                (2, "Expect expression.".to_string()),
+                (5, "Expect expression.".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,359 @@
+use std::fmt::Write;
+
+use crate::ast::{Expr, Function, LiteralValue, Stmt};
+
+/// Formats a program as parenthesized S-expressions, one per statement
+/// 
+/// ```
+/// use rlox::{printer, Parser, Scanner};
+/// 
+/// let (tokens, _) = Scanner::new("print -123 * (45.67);").scan_tokens();
+/// let program = Parser::new(tokens).parse().unwrap();
+/// 
+/// assert_eq!(printer::sexpr(&program), "(print (* (- 123) (group 45.67)))\n");
+/// ```
+/// 
+/// # Arguments
+/// 
+/// * `statements` - The program to format
+/// 
+/// # Returns
+/// 
+/// The formatted program, with a newline after each statement
+pub fn sexpr(statements: &[Stmt]) -> String {
+    statements
+        .iter()
+        .map(|statement| stmt_sexpr(statement) + "\n")
+        .collect()
+}
+
+/// Formats a single statement as an S-expression
+pub fn stmt_sexpr(statement: &Stmt) -> String {
+    match statement {
+        Stmt::Block { statements } => parenthesize("block", statements.iter().map(stmt_sexpr)),
+        Stmt::Class { name, superclass, methods } => {
+            let mut parts = vec![name.lexeme.clone()];
+            if let Some(superclass) = superclass {
+                parts.push("<".to_string());
+                parts.push(expr_sexpr(superclass));
+            }
+            parts.extend(methods.iter().map(|method| function_sexpr(method)));
+            parenthesize("class", parts)
+        }
+        Stmt::Expression { expression } => parenthesize(";", [expr_sexpr(expression)]),
+        Stmt::Function(function) => function_sexpr(function),
+        Stmt::If { condition, then_branch, else_branch } => {
+            let mut parts = vec![expr_sexpr(condition), stmt_sexpr(then_branch)];
+            parts.extend(else_branch.iter().map(|branch| stmt_sexpr(branch)));
+            parenthesize("if", parts)
+        }
+        Stmt::Print { expression } => parenthesize("print", [expr_sexpr(expression)]),
+        Stmt::Return { value, .. } => parenthesize("return", value.iter().map(expr_sexpr)),
+        Stmt::Var { name, initializer } => {
+            let mut parts = vec![name.lexeme.clone()];
+            parts.extend(initializer.iter().map(expr_sexpr));
+            parenthesize("var", parts)
+        }
+        Stmt::While { condition, body } => {
+            parenthesize("while", [expr_sexpr(condition), stmt_sexpr(body)])
+        }
+    }
+}
+
+/// Formats a single expression as an S-expression
+/// 
+/// ```
+/// use rlox::{printer, Parser, Scanner};
+/// 
+/// let (tokens, _) = Scanner::new("a.b = c or !d").scan_tokens();
+/// let expr = Parser::new(tokens).parse_expression().unwrap();
+/// 
+/// assert_eq!(printer::expr_sexpr(&expr), "(= (. a b) (or c (! d)))");
+/// ```
+pub fn expr_sexpr(expr: &Expr) -> String {
+    match expr {
+        Expr::Assign { name, value, .. } => parenthesize("=", [name.lexeme.clone(), expr_sexpr(value)]),
+        Expr::Binary { left, operator, right } => {
+            parenthesize(&operator.lexeme, [expr_sexpr(left), expr_sexpr(right)])
+        }
+        Expr::Call { callee, arguments, .. } => {
+            let mut parts = vec![expr_sexpr(callee)];
+            parts.extend(arguments.iter().map(expr_sexpr));
+            parenthesize("call", parts)
+        }
+        Expr::Get { object, name } => parenthesize(".", [expr_sexpr(object), name.lexeme.clone()]),
+        Expr::Grouping { expression } => parenthesize("group", [expr_sexpr(expression)]),
+        Expr::Literal { value } => literal(value),
+        Expr::Logical { left, operator, right } => {
+            parenthesize(&operator.lexeme, [expr_sexpr(left), expr_sexpr(right)])
+        }
+        Expr::Set { object, name, value } => {
+            let target = parenthesize(".", [expr_sexpr(object), name.lexeme.clone()]);
+            parenthesize("=", [target, expr_sexpr(value)])
+        }
+        Expr::Super { method, .. } => parenthesize("super", [method.lexeme.clone()]),
+        Expr::This { .. } => "this".to_string(),
+        Expr::Unary { operator, right } => parenthesize(&operator.lexeme, [expr_sexpr(right)]),
+        Expr::Variable { name, .. } => name.lexeme.clone(),
+    }
+}
+
+/// Formats a program as an indented tree, one node per line
+/// 
+/// Children are indented two spaces deeper than their parent.
+/// 
+/// ```
+/// use rlox::{printer, Parser, Scanner};
+/// 
+/// let (tokens, _) = Scanner::new("var a = 1 + 2;").scan_tokens();
+/// let program = Parser::new(tokens).parse().unwrap();
+/// 
+/// assert_eq!(
+///     printer::tree(&program),
+///     "Var a\n  Binary +\n    Literal 1\n    Literal 2\n"
+/// );
+/// ```
+/// 
+/// # Arguments
+/// 
+/// * `statements` - The program to format
+/// 
+/// # Returns
+/// 
+/// The formatted tree
+pub fn tree(statements: &[Stmt]) -> String {
+    let mut printer = TreePrinter::default();
+    for statement in statements {
+        printer.stmt(statement);
+    }
+    printer.output
+}
+
+/// Builds the indented tree form
+/// 
+/// # Fields
+/// 
+/// * `output` - The text written so far
+/// * `depth` - Indentation level of the next line
+#[derive(Default)]
+struct TreePrinter {
+    output: String,
+    depth: usize,
+}
+
+impl TreePrinter {
+    /// write one line at the current depth
+    fn line(&mut self, text: &str) {
+        writeln!(self.output, "{:indent$}{}", "", text, indent = self.depth * 2).unwrap();
+    }
+
+    /// write a line, then run `children` one level deeper
+    fn node(&mut self, text: &str, children: impl FnOnce(&mut Self)) {
+        self.line(text);
+        self.depth += 1;
+        children(self);
+        self.depth -= 1;
+    }
+
+    fn stmt(&mut self, statement: &Stmt) {
+        match statement {
+            Stmt::Block { statements } => self.node("Block", |p| {
+                statements.iter().for_each(|s| p.stmt(s));
+            }),
+            Stmt::Class { name, superclass, methods } => {
+                let header = match superclass {
+                    Some(superclass) => format!("Class {} < {}", name.lexeme, expr_sexpr(superclass)),
+                    None => format!("Class {}", name.lexeme),
+                };
+                self.node(&header, |p| methods.iter().for_each(|m| p.function(m)));
+            }
+            Stmt::Expression { expression } => self.node("Expression", |p| p.expr(expression)),
+            Stmt::Function(function) => self.function(function),
+            Stmt::If { condition, then_branch, else_branch } => self.node("If", |p| {
+                p.expr(condition);
+                p.node("Then", |p| p.stmt(then_branch));
+                if let Some(else_branch) = else_branch {
+                    p.node("Else", |p| p.stmt(else_branch));
+                }
+            }),
+            Stmt::Print { expression } => self.node("Print", |p| p.expr(expression)),
+            Stmt::Return { value, .. } => self.node("Return", |p| {
+                value.iter().for_each(|v| p.expr(v));
+            }),
+            Stmt::Var { name, initializer } => self.node(&format!("Var {}", name.lexeme), |p| {
+                initializer.iter().for_each(|i| p.expr(i));
+            }),
+            Stmt::While { condition, body } => self.node("While", |p| {
+                p.expr(condition);
+                p.node("Body", |p| p.stmt(body));
+            }),
+        }
+    }
+
+    fn function(&mut self, function: &Function) {
+        let params: Vec<&str> = function.params.iter().map(|p| p.lexeme.as_str()).collect();
+        let header = format!("Function {}({})", function.name.lexeme, params.join(", "));
+        self.node(&header, |p| function.body.iter().for_each(|s| p.stmt(s)));
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign { name, value, .. } => self.node(&format!("Assign {}", name.lexeme), |p| p.expr(value)),
+            Expr::Binary { left, operator, right } => {
+                self.node(&format!("Binary {}", operator.lexeme), |p| {
+                    p.expr(left);
+                    p.expr(right);
+                })
+            }
+            Expr::Call { callee, arguments, .. } => self.node("Call", |p| {
+                p.expr(callee);
+                arguments.iter().for_each(|a| p.expr(a));
+            }),
+            Expr::Get { object, name } => self.node(&format!("Get {}", name.lexeme), |p| p.expr(object)),
+            Expr::Grouping { expression } => self.node("Grouping", |p| p.expr(expression)),
+            Expr::Literal { value } => self.line(&format!("Literal {}", literal(value))),
+            Expr::Logical { left, operator, right } => {
+                self.node(&format!("Logical {}", operator.lexeme), |p| {
+                    p.expr(left);
+                    p.expr(right);
+                })
+            }
+            Expr::Set { object, name, value } => self.node(&format!("Set {}", name.lexeme), |p| {
+                p.expr(object);
+                p.expr(value);
+            }),
+            Expr::Super { method, .. } => self.line(&format!("Super {}", method.lexeme)),
+            Expr::This { .. } => self.line("This"),
+            Expr::Unary { operator, right } => {
+                self.node(&format!("Unary {}", operator.lexeme), |p| p.expr(right))
+            }
+            Expr::Variable { name, .. } => self.line(&format!("Variable {}", name.lexeme)),
+        }
+    }
+}
+
+/// format a function declaration as an S-expression
+fn function_sexpr(function: &Function) -> String {
+    let params: Vec<&str> = function.params.iter().map(|p| p.lexeme.as_str()).collect();
+    let mut parts = vec![function.name.lexeme.clone(), format!("({})", params.join(" "))];
+    parts.extend(function.body.iter().map(stmt_sexpr));
+    parenthesize("fun", parts)
+}
+
+/// format a literal value the way it would be written in source
+fn literal(value: &LiteralValue) -> String {
+    match value {
+        LiteralValue::Nil => "nil".to_string(),
+        LiteralValue::Bool(b) => b.to_string(),
+        LiteralValue::Number(n) => n.to_string(),
+        LiteralValue::String(s) => format!("{:?}", s),
+    }
+}
+
+/// wrap a name and its parts in parentheses
+fn parenthesize(name: &str, parts: impl IntoIterator<Item = String>) -> String {
+    let mut text = format!("({}", name);
+    for part in parts {
+        text.push(' ');
+        text.push_str(&part);
+    }
+    text.push(')');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
+
+    // 辅助函数：解析整个程序
+    fn parse(source: &str) -> Vec<Stmt> {
+        let (tokens, errors) = Scanner::new(source).scan_tokens();
+        assert!(errors.is_empty(), "测试源代码不应该有词法错误");
+        Parser::new(tokens).parse().unwrap()
+    }
+
+    #[test]
+    fn test_sexpr_statements() {
+        let source = "var a = 1;\nvar b;\n{ print a; }\nif (a) b = 2; else b = 3;\nwhile (b) b = nil;";
+        assert_eq!(
+            sexpr(&parse(source)),
+            "(var a 1)\n(var b)\n(block (print a))\n(if a (; (= b 2)) (; (= b 3)))\n(while b (; (= b nil)))\n"
+        );
+    }
+
+    #[test]
+    fn test_sexpr_functions_and_classes() {
+        let source = "fun f(a, b) { return a; } class B < A { m() { return; } }";
+        assert_eq!(
+            sexpr(&parse(source)),
+            "(fun f (a b) (return a))\n(class B < A (fun m () (return)))\n"
+        );
+    }
+
+    #[test]
+    fn test_sexpr_for_loop_shows_desugaring() {
+        assert_eq!(
+            sexpr(&parse("for (var i = 0; i < 2; i = i + 1) print i;")),
+            "(block (var i 0) (while (< i 2) (block (print i) (; (= i (+ i 1))))))\n"
+        );
+    }
+
+    #[test]
+    fn test_sexpr_expressions() {
+        let source = "print f(1, \"two\\n\").x;\nthis.y = super.z;\nprint true and !false or nil;";
+        assert_eq!(
+            sexpr(&parse(source)),
+            "(print (. (call f 1 \"two\\n\") x))\n(; (= (. this y) (super z)))\n(print (or (and true (! false)) nil))\n"
+        );
+    }
+
+    #[test]
+    fn test_tree_statements() {
+        let source = "if (a) { print -1; } else b = c.d;\nwhile (x or y) f(x);";
+        assert_eq!(
+            tree(&parse(source)),
+            "\
+If
+  Variable a
+  Then
+    Block
+      Print
+        Unary -
+          Literal 1
+  Else
+    Expression
+      Assign b
+        Get d
+          Variable c
+While
+  Logical or
+    Variable x
+    Variable y
+  Body
+    Expression
+      Call
+        Variable f
+        Variable x
+"
+        );
+    }
+
+    #[test]
+    fn test_tree_classes() {
+        let source = "class A < B { init(x) { this.x = (x); return; } }";
+        assert_eq!(
+            tree(&parse(source)),
+            "\
+Class A < B
+  Function init(x)
+    Expression
+      Set x
+        This
+        Grouping
+          Variable x
+    Return
+"
+        );
+    }
+}
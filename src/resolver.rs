@@ -0,0 +1,431 @@
+use std::{collections::HashMap, error::Error, fmt};
+
+use crate::{
+    ast::{Depth, Expr, Function, Stmt},
+    token::OwnedToken,
+};
+
+/// A semantic error found while resolving
+/// 
+/// # Fields
+/// 
+/// * `token` - The token where the error was detected
+/// * `message` - A description of the error
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolveError {
+    pub token: OwnedToken,
+    pub message: String,
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] {}", self.token.line, self.message)
+    }
+}
+
+impl Error for ResolveError {}
+
+/// The kind of function whose body is being resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FunctionKind {
+    None,
+    Function,
+    Initializer,
+    Method,
+}
+
+/// The kind of class whose body is being resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClassKind {
+    None,
+    Class,
+    Subclass,
+}
+
+/// Static pass that binds every variable reference to a scope
+/// 
+/// Walks the syntax tree once before it runs, recording in each
+/// reference's `Depth` how many scopes out its variable lives, and
+/// collecting errors that can be found without running the program.
+/// Globals are left unresolved and looked up dynamically.
+/// 
+/// # Fields
+/// 
+/// * `scopes` - The local scopes, innermost last; each maps a name to
+///   whether its initializer has been resolved
+/// * `function` - The kind of function being resolved
+/// * `class` - The kind of class being resolved
+/// * `errors` - The errors found so far
+#[derive(Debug)]
+pub struct Resolver {
+    scopes: Vec<HashMap<String, bool>>,
+    function: FunctionKind,
+    class: ClassKind,
+    errors: Vec<ResolveError>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Resolver::new()
+    }
+}
+
+impl Resolver {
+    /// Creates a resolver for top-level code
+    pub fn new() -> Resolver {
+        Resolver {
+            scopes: Vec::new(),
+            function: FunctionKind::None,
+            class: ClassKind::None,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Resolves a program
+    /// 
+    /// # Arguments
+    /// 
+    /// * `statements` - The program to resolve
+    /// 
+    /// # Returns
+    /// 
+    /// Every error found, in source order; the program must not run if
+    /// there are any
+    pub fn resolve(&mut self, statements: &[Stmt]) -> Result<(), Vec<ResolveError>> {
+        self.statements(statements);
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    /// resolve statements in order
+    fn statements(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    /// resolve a single statement
+    fn statement(&mut self, statement: &Stmt) {
+        match statement {
+            Stmt::Block { statements } => {
+                self.begin_scope();
+                self.statements(statements);
+                self.end_scope();
+            }
+            Stmt::Class { name, superclass, methods } => {
+                let enclosing = std::mem::replace(&mut self.class, ClassKind::Class);
+                self.declare(name);
+                self.define(name);
+
+                if let Some(superclass) = superclass {
+                    if let Expr::Variable { name: superclass_name, .. } = superclass {
+                        if superclass_name.lexeme == name.lexeme {
+                            self.error(superclass_name, "A class can't inherit from itself.");
+                        }
+                    }
+                    self.class = ClassKind::Subclass;
+                    self.expression(superclass);
+                    self.begin_scope();
+                    self.define_name("super");
+                }
+
+                self.begin_scope();
+                self.define_name("this");
+                for method in methods {
+                    let kind = if method.name.lexeme == "init" {
+                        FunctionKind::Initializer
+                    } else {
+                        FunctionKind::Method
+                    };
+                    self.function(method, kind);
+                }
+                self.end_scope();
+
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+                self.class = enclosing;
+            }
+            Stmt::Expression { expression } | Stmt::Print { expression } => self.expression(expression),
+            Stmt::Function(function) => {
+                self.declare(&function.name);
+                self.define(&function.name);
+                self.function(function, FunctionKind::Function);
+            }
+            Stmt::If { condition, then_branch, else_branch } => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Stmt::Return { keyword, value } => {
+                if self.function == FunctionKind::None {
+                    self.error(keyword, "Can't return from top-level code.");
+                }
+                if let Some(value) = value {
+                    if self.function == FunctionKind::Initializer {
+                        self.error(keyword, "Can't return a value from an initializer.");
+                    }
+                    self.expression(value);
+                }
+            }
+            Stmt::Var { name, initializer } => {
+                self.declare(name);
+                if let Some(initializer) = initializer {
+                    self.expression(initializer);
+                }
+                self.define(name);
+            }
+            Stmt::While { condition, body } => {
+                self.expression(condition);
+                self.statement(body);
+            }
+        }
+    }
+
+    /// resolve a function body in a new scope holding its parameters
+    fn function(&mut self, function: &Function, kind: FunctionKind) {
+        let enclosing = std::mem::replace(&mut self.function, kind);
+        self.begin_scope();
+        for param in &function.params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.statements(&function.body);
+        self.end_scope();
+        self.function = enclosing;
+    }
+
+    /// resolve an expression
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign { name, value, depth } => {
+                self.expression(value);
+                self.local(name, depth);
+            }
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Call { callee, arguments, .. } => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            Expr::Get { object, .. } => self.expression(object),
+            Expr::Grouping { expression } => self.expression(expression),
+            Expr::Literal { .. } => (),
+            Expr::Set { object, value, .. } => {
+                self.expression(value);
+                self.expression(object);
+            }
+            Expr::Super { keyword, depth, .. } => {
+                match self.class {
+                    ClassKind::None => self.error(keyword, "Can't use 'super' outside of a class."),
+                    ClassKind::Class => self.error(keyword, "Can't use 'super' in a class with no superclass."),
+                    ClassKind::Subclass => (),
+                }
+                self.local(keyword, depth);
+            }
+            Expr::This { keyword, depth } => {
+                if self.class == ClassKind::None {
+                    self.error(keyword, "Can't use 'this' outside of a class.");
+                    return;
+                }
+                self.local(keyword, depth);
+            }
+            Expr::Unary { right, .. } => self.expression(right),
+            Expr::Variable { name, depth } => {
+                let in_initializer = self
+                    .scopes
+                    .last()
+                    .is_some_and(|scope| scope.get(&name.lexeme) == Some(&false));
+                if in_initializer {
+                    self.error(name, "Can't read local variable in its own initializer.");
+                }
+                self.local(name, depth);
+            }
+        }
+    }
+
+    /// record how many scopes out a local variable is; globals are left unset
+    fn local(&mut self, name: &OwnedToken, depth: &Depth) {
+        let found = self.scopes.iter().rev().position(|scope| scope.contains_key(&name.lexeme));
+        if let Some(distance) = found {
+            depth.set(distance);
+        }
+    }
+
+    /// start a new innermost scope
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// leave the innermost scope
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// add a variable to the innermost scope, not yet usable
+    fn declare(&mut self, name: &OwnedToken) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+        if scope.insert(name.lexeme.clone(), false).is_some() {
+            self.error(name, "Already a variable with this name in this scope.");
+        }
+    }
+
+    /// mark a declared variable as initialized
+    fn define(&mut self, name: &OwnedToken) {
+        self.define_name(&name.lexeme);
+    }
+
+    /// mark a name as initialized in the innermost scope
+    fn define_name(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), true);
+        }
+    }
+
+    /// record an error at a token
+    fn error(&mut self, token: &OwnedToken, message: &str) {
+        self.errors.push(ResolveError {
+            token: token.clone(),
+            message: message.to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
+
+    // 辅助函数：解析并绑定程序，返回语法树和错误信息
+    fn resolve(source: &str) -> (Vec<Stmt>, Vec<String>) {
+        let (tokens, errors) = Scanner::new(source).scan_tokens();
+        assert!(errors.is_empty(), "测试源代码不应该有词法错误");
+        let statements = Parser::new(tokens).parse().expect("测试源代码不应该有语法错误");
+        let errors = match Resolver::new().resolve(&statements) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.iter().map(|error| error.to_string()).collect(),
+        };
+        (statements, errors)
+    }
+
+    // 辅助函数：返回绑定错误
+    fn errors(source: &str) -> Vec<String> {
+        resolve(source).1
+    }
+
+    // 辅助函数：取出表达式语句中的表达式
+    fn expression(statement: &Stmt) -> &Expr {
+        match statement {
+            Stmt::Expression { expression } | Stmt::Print { expression } => expression,
+            other => panic!("不是表达式语句: {:?}", other),
+        }
+    }
+
+    // 辅助函数：取出变量引用记录的作用域距离
+    fn depth(expr: &Expr) -> Option<usize> {
+        match expr {
+            Expr::Variable { depth, .. } | Expr::Assign { depth, .. } => depth.get(),
+            other => panic!("不是变量引用: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_globals_are_left_unresolved() {
+        let (statements, errors) = resolve("var a = 1; print a; a = 2;");
+        assert!(errors.is_empty());
+        assert_eq!(depth(expression(&statements[1])), None);
+        assert_eq!(depth(expression(&statements[2])), None);
+    }
+
+    #[test]
+    fn test_local_depths() {
+        let (statements, errors) = resolve("{ var a; { var b; print a; print b; a = 1; } }");
+        assert!(errors.is_empty());
+        let Stmt::Block { statements } = &statements[0] else { panic!() };
+        let Stmt::Block { statements } = &statements[1] else { panic!() };
+        assert_eq!(depth(expression(&statements[1])), Some(1));
+        assert_eq!(depth(expression(&statements[2])), Some(0));
+        assert_eq!(depth(expression(&statements[3])), Some(1));
+    }
+
+    #[test]
+    fn test_function_parameters_are_locals() {
+        let (statements, errors) = resolve("fun f(a) { print a; }");
+        assert!(errors.is_empty());
+        let Stmt::Function(function) = &statements[0] else { panic!() };
+        assert_eq!(depth(expression(&function.body[0])), Some(0));
+    }
+
+    #[test]
+    fn test_read_in_own_initializer() {
+        assert_eq!(
+            errors("{ var a = 1; { var a = a; } }"),
+            vec!["[line 1] Can't read local variable in its own initializer."]
+        );
+        // 全局变量不受此限制
+        assert!(errors("var a = a;").is_empty());
+    }
+
+    #[test]
+    fn test_redeclare_in_same_scope() {
+        assert_eq!(
+            errors("fun f() {\n  var a;\n  var a;\n}"),
+            vec!["[line 3] Already a variable with this name in this scope."]
+        );
+        assert_eq!(
+            errors("fun f(a, a) {}"),
+            vec!["[line 1] Already a variable with this name in this scope."]
+        );
+        assert!(errors("var a; var a;").is_empty());
+        assert!(errors("{ var a; { var a; } }").is_empty());
+    }
+
+    #[test]
+    fn test_return_errors() {
+        assert_eq!(errors("return 1;"), vec!["[line 1] Can't return from top-level code."]);
+        assert_eq!(
+            errors("class A { init() { return 1; } }"),
+            vec!["[line 1] Can't return a value from an initializer."]
+        );
+        assert!(errors("class A { init() { return; } }").is_empty());
+        assert!(errors("class A { init() { fun f() { return 1; } } }").is_empty());
+    }
+
+    #[test]
+    fn test_this_outside_class() {
+        assert_eq!(errors("print this;"), vec!["[line 1] Can't use 'this' outside of a class."]);
+        assert_eq!(errors("fun f() { this; }"), vec!["[line 1] Can't use 'this' outside of a class."]);
+        assert!(errors("class A { m() { fun f() { return this; } } }").is_empty());
+    }
+
+    #[test]
+    fn test_super_errors() {
+        assert_eq!(errors("super.m();"), vec!["[line 1] Can't use 'super' outside of a class."]);
+        assert_eq!(
+            errors("class A { m() { super.m(); } }"),
+            vec!["[line 1] Can't use 'super' in a class with no superclass."]
+        );
+        assert!(errors("class A {} class B < A { m() { super.m(); } }").is_empty());
+    }
+
+    #[test]
+    fn test_inherit_from_self() {
+        assert_eq!(errors("class A < A {}"), vec!["[line 1] A class can't inherit from itself."]);
+    }
+
+    #[test]
+    fn test_reports_every_error() {
+        assert_eq!(errors("return;\nprint this;").len(), 2);
+    }
+}
@@ -1,26 +1,135 @@
-#![allow(unused)]
-use crate::{lox::Lox, token::{Token, TokenType}};
+use std::{collections::VecDeque, error::Error, fmt, iter::FusedIterator};
+
+use crate::token::{Literal, Span, Token, TokenType};
+
+/// Reserved words and the token types they scan to
+const KEYWORDS: [(&str, TokenType); 16] = [
+    ("and", TokenType::And),
+    ("class", TokenType::Class),
+    ("else", TokenType::Else),
+    ("false", TokenType::False),
+    ("for", TokenType::For),
+    ("fun", TokenType::Fun),
+    ("if", TokenType::If),
+    ("nil", TokenType::Nil),
+    ("or", TokenType::Or),
+    ("print", TokenType::Print),
+    ("return", TokenType::Return),
+    ("super", TokenType::Super),
+    ("this", TokenType::This),
+    ("true", TokenType::True),
+    ("var", TokenType::Var),
+    ("while", TokenType::While),
+];
+
+/// look up the keyword token type for an identifier, if it is reserved
+fn keyword(text: &str) -> Option<TokenType> {
+    KEYWORDS
+        .iter()
+        .find(|(name, _)| *name == text)
+        .map(|(_, token_type)| *token_type)
+}
+
+/// A lexical error found while scanning
+/// 
+/// Displays as `line:column: message`.
+/// 
+/// # Fields
+/// 
+/// * `span` - Where the offending text sits in the source, including its line and column
+/// * `text` - The source text that could not be scanned
+/// * `message` - A description of the error
+/// * `at_end` - Whether the source ended before the offending construct
+///   did, as with an unterminated string or block comment
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanError {
+    pub span: Span,
+    pub text: String,
+    pub message: String,
+    pub at_end: bool,
+}
+
+impl ScanError {
+    /// Returns where the error is reported, for error messages
+    /// 
+    /// # Returns
+    /// 
+    /// ` at end` if the source ended inside the offending construct, and
+    /// ` at 'text'` otherwise
+    pub fn location(&self) -> String {
+        if self.at_end {
+            " at end".to_string()
+        } else {
+            format!(" at '{}'", self.text)
+        }
+    }
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.span.line, self.span.column, self.message)
+    }
+}
+
+impl Error for ScanError {}
+
+/// A position in the source code
+#[derive(Debug, Clone, Copy)]
+struct Position {
+    offset: usize,
+    line: usize,
+    column: usize,
+}
 
 /// Lexical scanner for the Lox language
 /// 
-/// Breaks source code into tokens for parsing.
+/// Breaks source code into tokens for parsing. The scanner borrows the
+/// source and is an iterator: each call to `next` scans just enough input
+/// to produce one token or one lexical error, so a consumer can pull
+/// tokens on demand. Lexical errors do not stop the scan.
+/// 
+/// # Examples
+/// 
+/// ```
+/// use rlox::{Literal, Scanner, TokenType};
+/// 
+/// let mut scanner = Scanner::new("print \"hi\" ~ 1.5;");
+/// 
+/// let print = scanner.next().unwrap().unwrap();
+/// assert_eq!(print.token_type, TokenType::Print);
+/// 
+/// let string = scanner.next().unwrap().unwrap();
+/// assert_eq!(string.lexeme, "\"hi\"");
+/// assert_eq!(string.literal, Some(Literal::String("hi".to_string())));
+/// 
+/// let error = scanner.next().unwrap().unwrap_err();
+/// assert_eq!(error.to_string(), "1:12: Unexpected character.");
+/// 
+/// let (rest, errors) = scanner.scan_tokens();
+/// assert_eq!(rest.len(), 2);
+/// assert!(errors.is_empty());
+/// ```
 /// 
 /// # Fields
 /// 
 /// * `source` - The source code being scanned
-/// * `start` - Start position of the current lexeme
-/// * `current` - Current position in the source code
+/// * `current` - Byte offset of the next character to be consumed
 /// * `line` - Current line number being processed
-/// * `tokens` - Collection of scanned tokens
-pub struct Scanner {
-    source: String,
-    start: usize,
+/// * `column` - 1-based column (in characters) of the next character
+/// * `token_start` - Where the current lexeme begins
+/// * `pending` - Items scanned ahead of the one being returned
+/// * `lossless` - Whether trivia and unscannable text are emitted as tokens
+pub struct Scanner<'src> {
+    source: &'src str,
     current: usize,
     line: usize,
-    pub tokens: Vec<Token>,
+    column: usize,
+    token_start: Position,
+    pending: VecDeque<Result<Token<'src>, ScanError>>,
+    lossless: bool,
 }
 
-impl Scanner {
+impl<'src> Scanner<'src> {
     /// Creates a new Scanner instance
     /// 
     /// # Arguments
@@ -30,26 +139,71 @@ impl Scanner {
     /// # Returns
     /// 
     /// A new Scanner initialized to the beginning of the source
-    pub fn new(source: String) -> Scanner {
+    pub fn new(source: &'src str) -> Scanner<'src> {
         Scanner {
             source,
-            start: 0,
             current: 0,
             line: 1,
-            tokens: vec![],
+            column: 1,
+            token_start: Position { offset: 0, line: 1, column: 1 },
+            pending: VecDeque::new(),
+            lossless: false,
+        }
+    }
+
+    /// Creates a Scanner that produces a lossless token stream
+    /// 
+    /// Whitespace and comments are emitted as `Whitespace` and `Comment`
+    /// tokens, and text that cannot be scanned is emitted as an `Error`
+    /// token in addition to its `ScanError`. Every byte of the source then
+    /// belongs to exactly one token, so concatenating the lexemes of all
+    /// tokens reproduces the source exactly.
+    /// 
+    /// ```
+    /// use rlox::Scanner;
+    /// 
+    /// let source = "var x = 1; // one\n/* two */ ~";
+    /// let text: String = Scanner::lossless(source)
+    ///     .filter_map(Result::ok)
+    ///     .map(|token| token.lexeme)
+    ///     .collect();
+    /// 
+    /// assert_eq!(text, source);
+    /// ```
+    /// 
+    /// # Arguments
+    /// 
+    /// * `source` - The source code to be scanned
+    /// 
+    /// # Returns
+    /// 
+    /// A new lossless Scanner initialized to the beginning of the source
+    pub fn lossless(source: &'src str) -> Scanner<'src> {
+        Scanner {
+            lossless: true,
+            ..Scanner::new(source)
         }
     }
 
     /// Scans the entire source code and produces tokens
     /// 
-    /// Processes the source code from start to finish, generating
-    /// a sequence of tokens for the parser.
-    pub fn scan_tokens(&mut self) {
-        while !self.is_at_end() {
-            self.start = self.current;
-            self.scan_token();
+    /// Drains the scanner into vectors. Scanning continues past lexical
+    /// errors so that every error in the source is reported.
+    /// 
+    /// # Returns
+    /// 
+    /// The scanned tokens and the lexical errors, each in source order
+    pub fn scan_tokens(&mut self) -> (Vec<Token<'src>>, Vec<ScanError>) {
+        let mut tokens = vec![];
+        let mut errors = vec![];
+        for item in self.by_ref() {
+            match item {
+                Ok(token) => tokens.push(token),
+                Err(error) => errors.push(error),
+            }
         }
 
+        (tokens, errors)
     }
 
     /// check if we are at the end of the source code
@@ -58,108 +212,378 @@ impl Scanner {
     }
 
     /// scan a single token
-    fn scan_token(&mut self) {
-        let c = self.advance();
-        match c {
-            '(' => self.add_token(TokenType::LeftParen, "("),
-            ')' => self.add_token(TokenType::RightParen, ")"),
-            '{' => self.add_token(TokenType::LeftBrace, "{"),
-            '}' => self.add_token(TokenType::RightBrace, "}"),
-            ',' => self.add_token(TokenType::Comma, ","),
-            '.' => self.add_token(TokenType::Dot, "."),
-            '-' => self.add_token(TokenType::Minus, "-"),
-            '+' => self.add_token(TokenType::Plus, "+"),
-            ';' => self.add_token(TokenType::Semicolon, ";"),
-            '*' => self.add_token(TokenType::Star, "*"),
+    ///
+    /// Returns `None` when the consumed text produces no token, such as
+    /// whitespace, comments, or text that was reported as an error.
+    fn scan_token(&mut self) -> Option<Token<'src>> {
+        let token_type = match self.advance() {
+            '(' => TokenType::LeftParen,
+            ')' => TokenType::RightParen,
+            '{' => TokenType::LeftBrace,
+            '}' => TokenType::RightBrace,
+            ',' => TokenType::Comma,
+            '.' => TokenType::Dot,
+            '-' => TokenType::Minus,
+            '+' => TokenType::Plus,
+            ';' => TokenType::Semicolon,
+            '*' => TokenType::Star,
             '!' => {
-                let token_type = if self.match_('=') {
-                    (TokenType::BangEqual, "!=")
+                if self.match_('=') {
+                    TokenType::BangEqual
                 } else {
-                    (TokenType::Bang, "!")
-                };
-                self.add_token(token_type.0, token_type.1)
+                    TokenType::Bang
+                }
             },
             '=' => {
-                let token_type = if self.match_('=') {
-                    (TokenType::EqualEqual, "==")
+                if self.match_('=') {
+                    TokenType::EqualEqual
                 } else {
-                    (TokenType::Equal, "=")
-                };
-                self.add_token(token_type.0, token_type.1)
+                    TokenType::Equal
+                }
             },
             '<' => {
-                let token_type = if self.match_('=') {
-                    (TokenType::LessEqual, "<=")
+                if self.match_('=') {
+                    TokenType::LessEqual
                 } else {
-                    (TokenType::Less, "<")
-                };
-                self.add_token(token_type.0, token_type.1)
+                    TokenType::Less
+                }
             },
             '>' => {
-                let token_type = if self.match_('=') {
-                    (TokenType::GreaterEqual, ">=")
+                if self.match_('=') {
+                    TokenType::GreaterEqual
                 } else {
-                    (TokenType::Greater, ">")
-                };
-                self.add_token(token_type.0, token_type.1)
+                    TokenType::Greater
+                }
             },
             '/' => {
                 if self.match_('/') {
-                    // 注释
-                    while !self.is_at_end() && self.peek() != '\n' {
-                        self.advance();
-                    }
-                } else {
-                    self.add_token(TokenType::Slash, "/");
+                    return self.line_comment();
+                }
+                if self.match_('*') {
+                    self.block_comment();
+                    return self.trivia(TokenType::Comment);
                 }
+                TokenType::Slash
+            },
+            '"' => return self.string(),
+            c if c.is_ascii_digit() => return Some(self.number()),
+            c if is_identifier_start(c) => return Some(self.identifier()),
+            c @ (' ' | '\r' | '\t' | '\n') => {
+                if c == '\n' {
+                    self.newline();
+                }
+                return self.whitespace();
             },
-            ' ' | '\r' | '\t' => (),
-            '\n' => self.line += 1,
             _ => {
-                Lox::error(self.line, "Unexpected character.");
-                panic!("Unexpected character.");
+                self.error(self.token_start, "Unexpected character.");
+                return self.trivia(TokenType::Error);
             },
+        };
+
+        Some(self.make_token(token_type))
+    }
+
+    /// scan the rest of a `//` comment
+    ///
+    /// Plain comments are discarded. A `///` doc comment (but not `////`)
+    /// becomes a `DocComment` token whose literal is the comment text, so
+    /// documentation tools can attach it to the declaration that follows.
+    fn line_comment(&mut self) -> Option<Token<'src>> {
+        let is_doc = !self.is_at_end()
+            && self.peek() == '/'
+            && self.peek_next() != Some('/');
+        while !self.is_at_end() && self.peek() != '\n' {
+            self.advance();
+        }
+
+        if !is_doc {
+            return self.trivia(TokenType::Comment);
+        }
+        let text = &self.current_lexeme()[3..];
+        let text = text.strip_prefix(' ').unwrap_or(text);
+        Some(self.make_literal(TokenType::DocComment, Literal::String(text.to_string())))
+    }
+
+    /// scan the rest of a run of whitespace
+    ///
+    /// Outside lossless mode each whitespace character is skipped on its
+    /// own; in lossless mode the whole run becomes one token.
+    fn whitespace(&mut self) -> Option<Token<'src>> {
+        if !self.lossless {
+            return None;
+        }
+        while !self.is_at_end() && matches!(self.peek(), ' ' | '\r' | '\t' | '\n') {
+            if self.advance() == '\n' {
+                self.newline();
+            }
+        }
+        self.trivia(TokenType::Whitespace)
+    }
+
+    /// skip the rest of a `/* ... */` comment
+    ///
+    /// Block comments nest, so every `/*` inside needs its own `*/`.
+    fn block_comment(&mut self) {
+        let mut depth = 1;
+        while depth > 0 {
+            if self.is_at_end() {
+                self.unterminated("Unterminated block comment.");
+                return;
+            }
+            match self.advance() {
+                '/' if self.match_('*') => depth += 1,
+                '*' if self.match_('/') => depth -= 1,
+                '\n' => self.newline(),
+                _ => (),
+            }
+        }
+    }
+
+    /// scan the rest of a string literal, decoding escape sequences
+    ///
+    /// The opening quote has already been consumed. Strings may span
+    /// multiple lines; the token is reported on the line where it ends.
+    fn string(&mut self) -> Option<Token<'src>> {
+        let mut value = String::new();
+        loop {
+            if self.is_at_end() {
+                self.unterminated("Unterminated string.");
+                return self.trivia(TokenType::Error);
+            }
+            let escape_start = self.cursor();
+            match self.advance() {
+                '"' => break,
+                '\\' => {
+                    if let Some(c) = self.escape(escape_start) {
+                        value.push(c);
+                    }
+                }
+                c => {
+                    if c == '\n' {
+                        self.newline();
+                    }
+                    value.push(c);
+                }
+            }
+        }
+
+        Some(self.make_literal(TokenType::String, Literal::String(value)))
+    }
+
+    /// scan the rest of a number literal
+    ///
+    /// A `.` only belongs to the number when a digit follows it, so `1.5`
+    /// is a single number while `1.` and `1.foo` end before the dot.
+    fn number(&mut self) -> Token<'src> {
+        self.digits();
+        if !self.is_at_end()
+            && self.peek() == '.'
+            && self.peek_next().is_some_and(|c| c.is_ascii_digit())
+        {
+            self.advance();
+            self.digits();
+        }
+
+        let value = self.current_lexeme().parse().unwrap();
+        self.make_literal(TokenType::Number, Literal::Number(value))
+    }
+
+    /// scan the rest of an identifier or reserved word
+    fn identifier(&mut self) -> Token<'src> {
+        while !self.is_at_end() && is_identifier_char(self.peek()) {
+            self.advance();
+        }
+
+        let token_type = keyword(self.current_lexeme()).unwrap_or(TokenType::Identifier);
+        self.make_token(token_type)
+    }
+
+    /// consume a run of decimal digits
+    fn digits(&mut self) {
+        while !self.is_at_end() && self.peek().is_ascii_digit() {
+            self.advance();
         }
     }
 
-    /// add a token to the list of tokens
-    fn add_token(&mut self, token_type: TokenType, lexeme: &str) {
-        self.tokens.push(Token {
-            token_type,
-            lexeme: lexeme.to_string(),
+    /// decode the escape sequence following a backslash
+    ///
+    /// Records an error and returns `None` for unknown or malformed escapes.
+    fn escape(&mut self, from: Position) -> Option<char> {
+        if self.is_at_end() {
+            return None;
+        }
+        match self.advance() {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            '"' => Some('"'),
+            '\\' => Some('\\'),
+            'u' => self.unicode_escape(from),
+            c => {
+                self.error(from, "Invalid escape sequence.");
+                if c == '\n' {
+                    self.newline();
+                }
+                None
+            }
+        }
+    }
+
+    /// decode the `{XXXX}` part of a `\u{XXXX}` escape
+    fn unicode_escape(&mut self, from: Position) -> Option<char> {
+        if !self.match_('{') {
+            self.error(from, "Expected '{' after '\\u'.");
+            return None;
+        }
+        let mut digits = String::new();
+        while !self.is_at_end() && self.peek().is_ascii_hexdigit() {
+            digits.push(self.advance());
+        }
+        if !self.match_('}') {
+            self.error(from, "Unterminated unicode escape.");
+            return None;
+        }
+        let c = u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32);
+        if c.is_none() {
+            self.error(from, "Invalid unicode escape.");
+        }
+        c
+    }
+
+    /// record a lexical error covering the source text from `from` to `current`
+    fn error(&mut self, from: Position, message: &str) {
+        self.push_error(from, message, false);
+    }
+
+    /// record a lexical error for a token that the end of the source cut short
+    fn unterminated(&mut self, message: &str) {
+        self.push_error(self.token_start, message, true);
+    }
+
+    /// queue a lexical error for the iterator to yield
+    fn push_error(&mut self, from: Position, message: &str, at_end: bool) {
+        let span = self.span_from(from);
+        self.pending.push_back(Err(ScanError {
+            span,
+            text: span.text(self.source).to_string(),
+            message: message.to_string(),
+            at_end,
+        }));
+    }
+
+    /// the current position in the source
+    fn cursor(&self) -> Position {
+        Position {
+            offset: self.current,
             line: self.line,
-        });
+            column: self.column,
+        }
+    }
+
+    /// the span from `from` up to the current position
+    fn span_from(&self, from: Position) -> Span {
+        Span::new(from.offset, self.current, from.line, from.column)
+    }
+
+    /// start a new line after consuming a newline character
+    fn newline(&mut self) {
+        self.line += 1;
+        self.column = 1;
+    }
+
+    /// make a token whose lexeme is the current source text
+    fn make_token(&self, token_type: TokenType) -> Token<'src> {
+        let span = self.span_from(self.token_start);
+        Token::new(token_type, span.text(self.source), self.line).with_span(span)
+    }
+
+    /// make a token for text that is only kept in lossless mode
+    fn trivia(&self, token_type: TokenType) -> Option<Token<'src>> {
+        self.lossless.then(|| self.make_token(token_type))
+    }
+
+    /// make a literal token whose lexeme is the current source text
+    fn make_literal(&self, token_type: TokenType, literal: Literal) -> Token<'src> {
+        let span = self.span_from(self.token_start);
+        Token::with_literal(token_type, span.text(self.source), literal, self.line).with_span(span)
+    }
+
+    /// the source text of the current lexeme
+    fn current_lexeme(&self) -> &'src str {
+        &self.source[self.token_start.offset..self.current]
     }
 
     /// advance the current position and return the character at the current position
+    ///
+    /// `current` moves by the UTF-8 width of the character, so it always
+    /// stays on a character boundary.
     fn advance(&mut self) -> char {
-        let c = self.source.chars().nth(self.current).unwrap();
-        self.current += 1;
+        let c = self.peek();
+        self.current += c.len_utf8();
+        self.column += 1;
         c
     }
 
     /// check if the current character matches the expected character
     fn match_(&mut self, expected: char) -> bool {
-        if self.is_at_end() {
+        if self.is_at_end() || self.peek() != expected {
             return false;
         }
-        if self.source.chars().nth(self.current).unwrap() != expected {
-            return false;
-        }
-        self.current += 1;
+        self.advance();
         true
     }
 
     /// peek at the next character
+    ///
+    /// ASCII bytes are returned directly; anything else is decoded from
+    /// the remaining source. Either way this is O(1).
     fn peek(&self) -> char {
-        self.source.chars().nth(self.current).unwrap()
+        match self.source.as_bytes().get(self.current) {
+            Some(&b) if b.is_ascii() => b as char,
+            _ => self.source[self.current..].chars().next().unwrap(),
+        }
     }
 
-    /// peek at the next character
+    /// peek at the character after the next one
     fn peek_next(&self) -> Option<char> {
-        self.source.chars().nth(self.current + 1)
+        self.source[self.current..].chars().nth(1)
+    }
+}
+
+impl<'src> Iterator for Scanner<'src> {
+    type Item = Result<Token<'src>, ScanError>;
+
+    /// scan until the next token or lexical error
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                return Some(item);
+            }
+            if self.is_at_end() {
+                return None;
+            }
+
+            self.token_start = self.cursor();
+            if let Some(token) = self.scan_token() {
+                if self.pending.is_empty() {
+                    return Some(Ok(token));
+                }
+                // errors found inside the token are reported before it
+                self.pending.push_back(Ok(token));
+            }
+        }
     }
-    
+}
+
+impl FusedIterator for Scanner<'_> {}
+
+/// check if a character can start an identifier
+fn is_identifier_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+/// check if a character can continue an identifier
+fn is_identifier_char(c: char) -> bool {
+    is_identifier_start(c) || c.is_ascii_digit()
 }
 
 #[cfg(test)]
@@ -168,54 +592,54 @@ mod tests {
 
     #[test]
     fn test_is_at_end_empty_source() {
-        let scanner = Scanner::new(String::new());
+        let scanner = Scanner::new("");
         assert!(scanner.is_at_end(), "Should be at end with empty source");
     }
 
     #[test]
     fn test_is_at_end_at_start() {
-        let scanner = Scanner::new("hello".to_string());
+        let scanner = Scanner::new("hello");
         assert!(!scanner.is_at_end(), "Should not be at end at start of source");
     }
 
     #[test]
     fn test_is_at_end_in_middle() {
-        let mut scanner = Scanner::new("hello".to_string());
+        let mut scanner = Scanner::new("hello");
         scanner.current = 2;
         assert!(!scanner.is_at_end(), "Should not be at end in middle of source");
     }
 
     #[test]
     fn test_is_at_end_at_end() {
-        let mut scanner = Scanner::new("hello".to_string());
+        let mut scanner = Scanner::new("hello");
         scanner.current = 5; // length of "hello"
         assert!(scanner.is_at_end(), "Should be at end of source");
     }
 
     #[test]
     fn test_is_at_end_past_end() {
-        let mut scanner = Scanner::new("hello".to_string());
+        let mut scanner = Scanner::new("hello");
         scanner.current = 6; // beyond length of "hello"
         assert!(scanner.is_at_end(), "Should be at end when past source length");
     }
 
     #[test]
     fn test_is_at_end_with_unicode() {
-        let mut scanner = Scanner::new("你好".to_string());
+        let mut scanner = Scanner::new("你好");
         scanner.current = 6; // UTF-8 byte length of "你好"
         assert!(scanner.is_at_end(), "Should handle end position correctly with Unicode string");
     }
 
     #[test]
     fn test_advance_single_char() {
-        let mut scanner = Scanner::new("a".to_string());
+        let mut scanner = Scanner::new("a");
         assert_eq!(scanner.advance(), 'a');
         assert_eq!(scanner.current, 1);
     }
 
     #[test]
     fn test_advance_multiple_chars() {
-        let mut scanner = Scanner::new("hello".to_string());
+        let mut scanner = Scanner::new("hello");
         assert_eq!(scanner.advance(), 'h');
         assert_eq!(scanner.advance(), 'e');
         assert_eq!(scanner.current, 2);
@@ -223,15 +647,28 @@ mod tests {
 
     #[test]
     fn test_advance_with_unicode() {
-        let mut scanner = Scanner::new("你好".to_string());
+        let mut scanner = Scanner::new("你好");
         assert_eq!(scanner.advance(), '你');
+        assert_eq!(scanner.current, 3, "current 是字节偏移");
         assert_eq!(scanner.advance(), '好');
-        assert_eq!(scanner.current, 2);
+        assert_eq!(scanner.current, 6);
+        assert!(scanner.is_at_end());
+    }
+
+    #[test]
+    fn test_advance_with_mixed_width_chars() {
+        let mut scanner = Scanner::new("aé你😀b");
+        let chars: Vec<char> = std::iter::from_fn(|| {
+            (!scanner.is_at_end()).then(|| scanner.advance())
+        })
+        .collect();
+        assert_eq!(chars, vec!['a', 'é', '你', '😀', 'b']);
+        assert_eq!(scanner.current, 1 + 2 + 3 + 4 + 1);
     }
 
     #[test]
     fn test_advance_with_special_chars() {
-        let mut scanner = Scanner::new("a\n\t".to_string());
+        let mut scanner = Scanner::new("a\n\t");
         assert_eq!(scanner.advance(), 'a');
         assert_eq!(scanner.advance(), '\n');
         assert_eq!(scanner.advance(), '\t');
@@ -241,14 +678,14 @@ mod tests {
     #[test]
     #[should_panic(expected = "called `Option::unwrap()` on a `None` value")]
     fn test_advance_at_end() {
-        let mut scanner = Scanner::new("a".to_string());
+        let mut scanner = Scanner::new("a");
         scanner.advance(); // 消费 'a'
         scanner.advance(); // 应该 panic，因为已经到达末尾
     }
 
     #[test]
     fn test_match_with_matching_char() {
-        let mut scanner = Scanner::new("==".to_string());
+        let mut scanner = Scanner::new("==");
         scanner.advance(); // 移动到第一个 '=' 之后
         assert!(scanner.match_('='), "应该匹配第二个 '='");
         assert_eq!(scanner.current, 2);
@@ -256,7 +693,7 @@ mod tests {
 
     #[test]
     fn test_match_with_non_matching_char() {
-        let mut scanner = Scanner::new("=!".to_string());
+        let mut scanner = Scanner::new("=!");
         scanner.advance(); // 移动到 '=' 之后
         assert!(!scanner.match_('='), "不应该匹配 '='");
         assert_eq!(scanner.current, 1); // current 不应该改变
@@ -264,7 +701,7 @@ mod tests {
 
     #[test]
     fn test_match_at_end() {
-        let mut scanner = Scanner::new("=".to_string());
+        let mut scanner = Scanner::new("=");
         scanner.advance(); // 移动到末尾
         assert!(!scanner.match_('='), "在末尾不应该匹配任何字符");
         assert_eq!(scanner.current, 1);
@@ -272,15 +709,15 @@ mod tests {
 
     #[test]
     fn test_match_with_unicode() {
-        let mut scanner = Scanner::new("你好".to_string());
+        let mut scanner = Scanner::new("你好");
         scanner.advance(); // 移动到 '你' 之后
         assert!(scanner.match_('好'), "应该匹配 '好' 字");
-        assert_eq!(scanner.current, 2);
+        assert_eq!(scanner.current, 6);
     }
 
     #[test]
     fn test_match_with_special_chars() {
-        let mut scanner = Scanner::new("\n\n".to_string());
+        let mut scanner = Scanner::new("\n\n");
         scanner.advance(); // 移动到第一个换行符之后
         assert!(scanner.match_('\n'), "应该匹配第二个换行符");
         assert_eq!(scanner.current, 2);
@@ -288,19 +725,19 @@ mod tests {
 
     #[test]
     fn test_peek_next_empty_source() {
-        let scanner = Scanner::new(String::new());
+        let scanner = Scanner::new("");
         assert_eq!(scanner.peek_next(), None, "空源代码应该返回 None");
     }
 
     #[test]
     fn test_peek_next_single_char() {
-        let scanner = Scanner::new("a".to_string());
+        let scanner = Scanner::new("a");
         assert_eq!(scanner.peek_next(), None, "单字符源代码在开始位置应该返回 None");
     }
 
     #[test]
     fn test_peek_next_multiple_chars() {
-        let mut scanner = Scanner::new("hello".to_string());
+        let mut scanner = Scanner::new("hello");
         assert_eq!(scanner.peek_next(), Some('e'), "应该返回第二个字符");
         
         scanner.current = 3; // 移动到 'l'
@@ -309,33 +746,41 @@ mod tests {
 
     #[test]
     fn test_peek_next_at_end() {
-        let mut scanner = Scanner::new("hi".to_string());
+        let mut scanner = Scanner::new("hi");
         scanner.current = 1; // 移动到最后一个字符
         assert_eq!(scanner.peek_next(), None, "在最后一个字符时应该返回 None");
     }
 
     #[test]
     fn test_peek_next_with_unicode() {
-        let scanner = Scanner::new("你好世界".to_string());
+        let scanner = Scanner::new("你好世界");
         assert_eq!(scanner.peek_next(), Some('好'), "应该正确返回下一个 Unicode 字符");
     }
 
+    #[test]
+    fn test_peek_next_after_unicode() {
+        let mut scanner = Scanner::new("你好世界");
+        scanner.advance();
+        assert_eq!(scanner.peek(), '好');
+        assert_eq!(scanner.peek_next(), Some('世'));
+    }
+
     #[test]
     fn test_peek_next_with_special_chars() {
-        let scanner = Scanner::new("a\n\t".to_string());
+        let scanner = Scanner::new("a\n\t");
         assert_eq!(scanner.peek_next(), Some('\n'), "应该正确返回特殊字符");
     }
 
     // 辅助函数：提取Token类型的向量
     fn get_token_types(tokens: &[Token]) -> Vec<TokenType> {
-        tokens.iter().map(|t| t.token_type.clone()).collect()
+        tokens.iter().map(|t| t.token_type).collect()
     }
 
     #[test]
     fn test_single_character_tokens() {
         let source = "(){},.-+;*";
-        let mut scanner = Scanner::new(source.to_string());
-        scanner.scan_tokens();
+        let mut scanner = Scanner::new(source);
+        let (tokens, _) = scanner.scan_tokens();
         
         let expected = vec![
             TokenType::LeftParen,
@@ -349,13 +794,13 @@ mod tests {
             TokenType::Semicolon,
             TokenType::Star,
         ];
-        assert_eq!(get_token_types(&scanner.tokens), expected);
+        assert_eq!(get_token_types(&tokens), expected);
     }
 
     #[test]
     fn test_double_character_tokens() {
-        let mut scanner = Scanner::new("!= == <= >= = < >".to_string());
-        scanner.scan_tokens();
+        let mut scanner = Scanner::new("!= == <= >= = < >");
+        let (tokens, _) = scanner.scan_tokens();
         
         let expected = vec![
             TokenType::BangEqual,
@@ -366,80 +811,692 @@ mod tests {
             TokenType::Less,
             TokenType::Greater,
         ];
-        assert_eq!(get_token_types(&scanner.tokens), expected);
+        assert_eq!(get_token_types(&tokens), expected);
     }
 
     #[test]
     fn test_comment_skipping() {
-        let mut scanner = Scanner::new("// comment\n+".to_string());
-        scanner.scan_tokens();
+        let mut scanner = Scanner::new("// comment\n+");
+        let (tokens, _) = scanner.scan_tokens();
         
-        assert_eq!(scanner.tokens.len(), 1);
-        assert_eq!(scanner.tokens[0].token_type, TokenType::Plus);
-        assert_eq!(scanner.tokens[0].line, 2);
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token_type, TokenType::Plus);
+        assert_eq!(tokens[0].line, 2);
     }
 
     #[test]
     fn test_whitespace_handling() {
-        let mut scanner = Scanner::new("  \t\r\n".to_string());
-        scanner.scan_tokens();
+        let mut scanner = Scanner::new("  \t\r\n");
+        let (tokens, _) = scanner.scan_tokens();
         
-        assert!(scanner.tokens.is_empty());
+        assert!(tokens.is_empty());
         assert_eq!(scanner.line, 2);
     }
 
     #[test]
     fn test_line_counting() {
-        let mut scanner = Scanner::new("+\n-\n*".to_string());
-        scanner.scan_tokens();
+        let mut scanner = Scanner::new("+\n-\n*");
+        let (tokens, _) = scanner.scan_tokens();
         
-        assert_eq!(scanner.tokens[0].line, 1);
-        assert_eq!(scanner.tokens[1].line, 2);
-        assert_eq!(scanner.tokens[2].line, 3);
+        assert_eq!(tokens[0].line, 1);
+        assert_eq!(tokens[1].line, 2);
+        assert_eq!(tokens[2].line, 3);
     }
 
     #[test]
     fn test_mixed_operators() {
-        let mut scanner = Scanner::new("!===// test".to_string());
-        scanner.scan_tokens();
+        let mut scanner = Scanner::new("!===// test");
+        let (tokens, _) = scanner.scan_tokens();
         
         let expected = vec![
             TokenType::BangEqual,
             TokenType::EqualEqual,
         ];
-        assert_eq!(get_token_types(&scanner.tokens), expected);
+        assert_eq!(get_token_types(&tokens), expected);
     }
 
     #[test]
     fn test_unterminated_comment() {
-        let mut scanner = Scanner::new("// This comment has no newline".to_string());
-        scanner.scan_tokens();
+        let mut scanner = Scanner::new("// This comment has no newline");
+        let (tokens, _) = scanner.scan_tokens();
         
-        assert!(scanner.tokens.is_empty());
+        assert!(tokens.is_empty());
         assert_eq!(scanner.line, 1);
     }
 
     #[test]
-    #[should_panic(expected = "Unexpected character.")]
     fn test_invalid_character() {
-        let mut scanner = Scanner::new("~".to_string());
-        scanner.scan_tokens();
+        let mut scanner = Scanner::new("~");
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert!(tokens.is_empty());
+        assert_eq!(
+            errors,
+            &[ScanError {
+                span: Span::new(0, 1, 1, 1),
+                text: "~".to_string(),
+                message: "Unexpected character.".to_string(),
+                at_end: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_error_locations() {
+        // 只有源代码在结构内部结束时才报告在末尾，与错误所在的位置无关
+        let location = |source| Scanner::new(source).scan_tokens().1[0].location();
+        assert_eq!(location("1 + ~"), " at '~'");
+        assert_eq!(location("\"abc"), " at end");
+        assert_eq!(location("/* abc"), " at end");
+        assert_eq!(location("\"a\\q\""), " at '\\q'");
+    }
+
+    #[test]
+    fn test_scanning_continues_after_errors() {
+        let mut scanner = Scanner::new("+ ~ -\n  @ *");
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert_eq!(
+            get_token_types(&tokens),
+            vec![TokenType::Plus, TokenType::Minus, TokenType::Star]
+        );
+        let positions: Vec<(usize, usize, &str)> = errors
+            .iter()
+            .map(|e| (e.span.line, e.span.column, e.text.as_str()))
+            .collect();
+        assert_eq!(positions, vec![(1, 3, "~"), (2, 3, "@")]);
     }
 
     #[test]
     fn test_multiline_processing() {
-        let mut scanner = Scanner::new("{\n// comment\n}".to_string());
-        scanner.scan_tokens();
+        let mut scanner = Scanner::new("{\n// comment\n}");
+        let (tokens, _) = scanner.scan_tokens();
         
-        assert_eq!(scanner.tokens.len(), 2);
-        assert_eq!(scanner.tokens[0].line, 1);
-        assert_eq!(scanner.tokens[1].line, 3);
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[0].line, 1);
+        assert_eq!(tokens[1].line, 3);
+    }
+
+    // 辅助函数：扫描单个字符串字面量并返回其解码后的值
+    fn scan_string(source: &str) -> Option<String> {
+        let mut scanner = Scanner::new(source);
+        let (tokens, _) = scanner.scan_tokens();
+        match tokens.first().and_then(|t| t.literal.clone()) {
+            Some(Literal::String(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_string_literal() {
+        let mut scanner = Scanner::new("\"hello\"");
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token_type, TokenType::String);
+        assert_eq!(tokens[0].lexeme, "\"hello\"");
+        assert_eq!(tokens[0].literal, Some(Literal::String("hello".to_string())));
+    }
+
+    #[test]
+    fn test_empty_string_literal() {
+        assert_eq!(scan_string("\"\""), Some(String::new()));
+    }
+
+    #[test]
+    fn test_multiline_string_tracks_lines() {
+        let mut scanner = Scanner::new("\"a\nb\nc\" +");
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(tokens[0].literal, Some(Literal::String("a\nb\nc".to_string())));
+        assert_eq!(tokens[0].line, 3);
+        assert_eq!(tokens[1].line, 3);
+    }
+
+    #[test]
+    fn test_string_escape_sequences() {
+        assert_eq!(scan_string(r#""a\nb""#), Some("a\nb".to_string()));
+        assert_eq!(scan_string(r#""a\tb""#), Some("a\tb".to_string()));
+        assert_eq!(scan_string(r#""say \"hi\"""#), Some("say \"hi\"".to_string()));
+        assert_eq!(scan_string(r#""back\\slash""#), Some("back\\slash".to_string()));
     }
 
     #[test]
-    #[should_panic(expected = "Unexpected character.")]
-    fn test_number_literal_not_implemented() {
-        let mut scanner = Scanner::new("123".to_string());
-        scanner.scan_tokens();
+    fn test_string_keeps_raw_lexeme() {
+        let mut scanner = Scanner::new(r#""a\tb""#);
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(tokens[0].lexeme, r#""a\tb""#);
+        assert_eq!(tokens[0].literal, Some(Literal::String("a\tb".to_string())));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_string_unicode_escape() {
+        assert_eq!(scan_string(r#""\u{41}""#), Some("A".to_string()));
+        assert_eq!(scan_string(r#""\u{4f60}\u{597D}""#), Some("你好".to_string()));
+        assert_eq!(scan_string(r#""\u{1F600}""#), Some("\u{1F600}".to_string()));
+    }
+
+    #[test]
+    fn test_string_invalid_escapes_are_dropped() {
+        assert_eq!(scan_string(r#""a\qb""#), Some("ab".to_string()));
+        assert_eq!(scan_string(r#""\u{D800}""#), Some(String::new()));
+        assert_eq!(scan_string(r#""\u{}x""#), Some("x".to_string()));
+        assert_eq!(scan_string(r#""\u41""#), Some("41".to_string()));
+    }
+
+    #[test]
+    fn test_string_with_unicode_content() {
+        assert_eq!(scan_string("\"你好\""), Some("你好".to_string()));
+        assert_eq!(scan_string("\"naïve 😀\""), Some("naïve 😀".to_string()));
+    }
+
+    #[test]
+    fn test_unicode_identifier_is_an_error() {
+        let mut scanner = Scanner::new("a 变量 b");
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert_eq!(get_token_types(&tokens), vec![TokenType::Identifier, TokenType::Identifier]);
+        assert_eq!(tokens[1].lexeme, "b");
+        let positions: Vec<(usize, &str)> = errors.iter().map(|e| (e.span.column, e.text.as_str())).collect();
+        assert_eq!(positions, vec![(3, "变"), (4, "量")], "列号按字符计算");
+    }
+
+    #[test]
+    fn test_unterminated_string() {
+        let mut scanner = Scanner::new("+ \"never closed\n+");
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert_eq!(get_token_types(&tokens), vec![TokenType::Plus], "未闭合的字符串不应该产生 token");
+        assert_eq!(scanner.line, 2);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Unterminated string.");
+        assert_eq!((errors[0].span.line, errors[0].span.column), (1, 3));
+        assert_eq!(errors[0].text, "\"never closed\n+");
+    }
+
+    #[test]
+    fn test_invalid_escape_is_reported() {
+        let mut scanner = Scanner::new("\"ok\\q\" \"\\u{110000}\"");
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert_eq!(tokens.len(), 2, "字符串本身仍然应该产生 token");
+        let reported: Vec<(usize, &str, &str)> = errors
+            .iter()
+            .map(|e| (e.span.column, e.text.as_str(), e.message.as_str()))
+            .collect();
+        assert_eq!(
+            reported,
+            vec![
+                (4, "\\q", "Invalid escape sequence."),
+                (9, "\\u{110000}", "Invalid unicode escape."),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_column_after_multiline_string() {
+        let mut scanner = Scanner::new("\"a\nbc\" ~");
+        let (_, errors) = scanner.scan_tokens();
+
+        assert_eq!((errors[0].span.line, errors[0].span.column), (2, 5));
+    }
+
+    // 辅助函数：提取所有数字字面量的值
+    fn get_numbers(tokens: &[Token]) -> Vec<f64> {
+        tokens
+            .iter()
+            .filter_map(|t| match t.literal {
+                Some(Literal::Number(n)) => Some(n),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_integer_literal() {
+        let mut scanner = Scanner::new("123");
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(get_token_types(&tokens), vec![TokenType::Number]);
+        assert_eq!(tokens[0].lexeme, "123");
+        assert_eq!(tokens[0].literal, Some(Literal::Number(123.0)));
+    }
+
+    #[test]
+    fn test_decimal_literal() {
+        let mut scanner = Scanner::new("1.5 0.25");
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(get_token_types(&tokens), vec![TokenType::Number, TokenType::Number]);
+        assert_eq!(tokens[0].lexeme, "1.5");
+        assert_eq!(get_numbers(&tokens), vec![1.5, 0.25]);
+    }
+
+    #[test]
+    fn test_trailing_dot_is_not_part_of_number() {
+        let mut scanner = Scanner::new("1.");
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(get_token_types(&tokens), vec![TokenType::Number, TokenType::Dot]);
+        assert_eq!(tokens[0].lexeme, "1");
+        assert_eq!(get_numbers(&tokens), vec![1.0]);
+    }
+
+    #[test]
+    fn test_leading_dot_is_not_part_of_number() {
+        let mut scanner = Scanner::new(".5");
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(get_token_types(&tokens), vec![TokenType::Dot, TokenType::Number]);
+        assert_eq!(get_numbers(&tokens), vec![5.0]);
+    }
+
+    #[test]
+    fn test_number_followed_by_dot_and_number() {
+        let mut scanner = Scanner::new("1.5.2");
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(
+            get_token_types(&tokens),
+            vec![TokenType::Number, TokenType::Dot, TokenType::Number]
+        );
+        assert_eq!(get_numbers(&tokens), vec![1.5, 2.0]);
+    }
+
+    #[test]
+    fn test_negative_number_is_minus_then_number() {
+        let mut scanner = Scanner::new("-3");
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(get_token_types(&tokens), vec![TokenType::Minus, TokenType::Number]);
+        assert_eq!(get_numbers(&tokens), vec![3.0]);
+    }
+
+    #[test]
+    fn test_leading_zeros() {
+        let mut scanner = Scanner::new("007");
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(tokens[0].lexeme, "007");
+        assert_eq!(get_numbers(&tokens), vec![7.0]);
+    }
+
+    #[test]
+    fn test_very_long_digit_run() {
+        let digits = "9".repeat(400);
+        let mut scanner = Scanner::new(&digits);
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].lexeme, digits);
+        assert_eq!(get_numbers(&tokens), vec![f64::INFINITY]);
+
+        let mut scanner = Scanner::new("12345678901234567890.5");
+        let (tokens, _) = scanner.scan_tokens();
+        assert_eq!(get_numbers(&tokens), vec![12345678901234567890.5]);
+    }
+
+    #[test]
+    fn test_numbers_in_expression() {
+        let mut scanner = Scanner::new("1+2.5*3");
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(
+            get_token_types(&tokens),
+            vec![
+                TokenType::Number,
+                TokenType::Plus,
+                TokenType::Number,
+                TokenType::Star,
+                TokenType::Number,
+            ]
+        );
+        assert_eq!(get_numbers(&tokens), vec![1.0, 2.5, 3.0]);
+    }
+
+    #[test]
+    fn test_identifier() {
+        let mut scanner = Scanner::new("printer _tmp x1 camelCase");
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(get_token_types(&tokens), vec![TokenType::Identifier; 4]);
+        let lexemes: Vec<&str> = tokens.iter().map(|t| t.lexeme).collect();
+        assert_eq!(lexemes, vec!["printer", "_tmp", "x1", "camelCase"]);
+        assert!(tokens.iter().all(|t| t.literal.is_none()));
+    }
+
+    #[test]
+    fn test_every_keyword_round_trips() {
+        for (name, token_type) in KEYWORDS.iter() {
+            let mut scanner = Scanner::new(name);
+            let (tokens, _) = scanner.scan_tokens();
+
+            assert_eq!(tokens.len(), 1, "{} 应该是单个 token", name);
+            assert_eq!(&tokens[0].token_type, token_type);
+            assert_eq!(tokens[0].lexeme, *name);
+        }
+    }
+
+    #[test]
+    fn test_near_miss_keywords_stay_identifiers() {
+        let source = "classy or_else printer iff _var Var WHILE nil0 thisx";
+        let mut scanner = Scanner::new(source);
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(get_token_types(&tokens), vec![TokenType::Identifier; 9]);
+    }
+
+    #[test]
+    fn test_var_declaration() {
+        let mut scanner = Scanner::new("var x = 1;");
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(
+            get_token_types(&tokens),
+            vec![
+                TokenType::Var,
+                TokenType::Identifier,
+                TokenType::Equal,
+                TokenType::Number,
+                TokenType::Semicolon,
+            ]
+        );
+        assert_eq!(tokens[1].lexeme, "x");
+    }
+
+    #[test]
+    fn test_number_followed_by_method_call() {
+        let mut scanner = Scanner::new("1.method");
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(
+            get_token_types(&tokens),
+            vec![TokenType::Number, TokenType::Dot, TokenType::Identifier]
+        );
+    }
+
+    #[test]
+    fn test_token_spans() {
+        let source = "var x =\n  \"hi\" >= 1.5;";
+        let mut scanner = Scanner::new(source);
+        let (tokens, _) = scanner.scan_tokens();
+
+        let spans: Vec<(usize, usize, usize, usize)> = tokens
+            .iter()
+            .map(|t| (t.span.start, t.span.end, t.span.line, t.span.column))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                (0, 3, 1, 1),
+                (4, 5, 1, 5),
+                (6, 7, 1, 7),
+                (10, 14, 2, 3),
+                (15, 17, 2, 8),
+                (18, 21, 2, 11),
+                (21, 22, 2, 14),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lexeme_is_sliced_from_source() {
+        let source = "print \"a\\tb\" != (3.25 +\n你好) // done";
+        let mut scanner = Scanner::new(source);
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert_eq!(errors.len(), 2);
+        for token in tokens {
+            assert_eq!(token.span.text(source), token.lexeme);
+        }
+        for error in errors {
+            assert_eq!(error.span.text(source), error.text);
+        }
+    }
+
+    #[test]
+    fn test_span_columns_count_characters() {
+        let source = "\"你好\" + x";
+        let mut scanner = Scanner::new(source);
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(tokens[0].span, Span::new(0, 8, 1, 1));
+        assert_eq!(tokens[1].span, Span::new(9, 10, 1, 6));
+        assert_eq!(tokens[2].span.text(source), "x");
+        assert_eq!(tokens[2].span.column, 8);
+    }
+
+    #[test]
+    fn test_multiline_string_span_starts_at_opening_quote() {
+        let source = "+ \"a\nb\"";
+        let mut scanner = Scanner::new(source);
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(tokens[1].span, Span::new(2, 7, 1, 3));
+        assert_eq!(tokens[1].line, 2, "line 仍然是 token 结束的行");
+    }
+
+    #[test]
+    fn test_scanner_is_lazy() {
+        let source = format!("1 + {}", "~".repeat(10_000));
+        let mut scanner = Scanner::new(&source);
+
+        let first = scanner.next().unwrap().unwrap();
+        assert_eq!(first.token_type, TokenType::Number);
+        assert_eq!(scanner.current, 1, "只应该扫描第一个 token");
+
+        let second = scanner.next().unwrap().unwrap();
+        assert_eq!(second.token_type, TokenType::Plus);
+        assert_eq!(scanner.current, 3);
+    }
+
+    #[test]
+    fn test_iterator_interleaves_errors_in_source_order() {
+        let items: Vec<Result<TokenType, String>> = Scanner::new("+ ~ \"a\\q\" -")
+            .map(|item| item.map(|t| t.token_type).map_err(|e| e.text))
+            .collect();
+
+        assert_eq!(
+            items,
+            vec![
+                Ok(TokenType::Plus),
+                Err("~".to_string()),
+                Err("\\q".to_string()),
+                Ok(TokenType::String),
+                Ok(TokenType::Minus),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_iterator_is_fused_at_end() {
+        let mut scanner = Scanner::new("x");
+        assert!(scanner.next().is_some());
+        assert!(scanner.next().is_none());
+        assert!(scanner.next().is_none());
+    }
+
+    #[test]
+    fn test_lexemes_borrow_from_source() {
+        let source = String::from("foo + bar");
+        let tokens: Vec<Token> = Scanner::new(&source).filter_map(Result::ok).collect();
+
+        let range = source.as_bytes().as_ptr_range();
+        for token in &tokens {
+            assert!(range.contains(&token.lexeme.as_ptr()), "lexeme 应该指向源代码而不是拷贝");
+        }
+    }
+
+    #[test]
+    fn test_block_comment_skipping() {
+        let mut scanner = Scanner::new("+ /* comment */ -");
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert_eq!(get_token_types(&tokens), vec![TokenType::Plus, TokenType::Minus]);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_block_comment_counts_lines() {
+        let mut scanner = Scanner::new("/* one\ntwo\n*/ +\n-");
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(tokens[0].line, 3);
+        assert_eq!(tokens[0].span.column, 4);
+        assert_eq!(tokens[1].line, 4);
+    }
+
+    #[test]
+    fn test_nested_block_comments() {
+        let mut scanner = Scanner::new("/* a /* b /* c */ */ still comment */ +");
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert_eq!(get_token_types(&tokens), vec![TokenType::Plus]);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_block_comment_edge_cases() {
+        let mut scanner = Scanner::new("/**/ /***/ /*/ */ /* // */ * /");
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert_eq!(get_token_types(&tokens), vec![TokenType::Star, TokenType::Slash]);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        let mut scanner = Scanner::new("+\n/* a /* b */\n-");
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert_eq!(get_token_types(&tokens), vec![TokenType::Plus]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Unterminated block comment.");
+        assert_eq!((errors[0].span.line, errors[0].span.column), (2, 1));
+        assert_eq!(scanner.line, 3);
+    }
+
+    #[test]
+    fn test_doc_comment_token() {
+        let mut scanner = Scanner::new("/// Adds two numbers.\nfun add");
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(
+            get_token_types(&tokens),
+            vec![TokenType::DocComment, TokenType::Fun, TokenType::Identifier]
+        );
+        assert_eq!(tokens[0].lexeme, "/// Adds two numbers.");
+        assert_eq!(tokens[0].literal, Some(Literal::String("Adds two numbers.".to_string())));
+        assert_eq!(tokens[0].line, 1);
+    }
+
+    #[test]
+    fn test_doc_comment_text() {
+        let mut scanner = Scanner::new("///\n///x\n///   indented");
+        let (tokens, _) = scanner.scan_tokens();
+
+        let texts: Vec<Option<Literal>> = tokens.into_iter().map(|t| t.literal).collect();
+        assert_eq!(
+            texts,
+            vec![
+                Some(Literal::String(String::new())),
+                Some(Literal::String("x".to_string())),
+                Some(Literal::String("  indented".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_four_slashes_is_a_plain_comment() {
+        let mut scanner = Scanner::new("//// not docs\n// nor this\n+");
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(get_token_types(&tokens), vec![TokenType::Plus]);
+    }
+
+    #[test]
+    fn test_lossless_trivia_tokens() {
+        let source = "a  // note\n\t/* block */b";
+        let (tokens, errors) = Scanner::lossless(source).scan_tokens();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            get_token_types(&tokens),
+            vec![
+                TokenType::Identifier,
+                TokenType::Whitespace,
+                TokenType::Comment,
+                TokenType::Whitespace,
+                TokenType::Comment,
+                TokenType::Identifier,
+            ]
+        );
+        assert_eq!(tokens[3].lexeme, "\n\t");
+        assert_eq!(tokens[5].line, 2);
+        assert_eq!(tokens[5].span.column, 13);
+    }
+
+    #[test]
+    fn test_lossless_error_tokens() {
+        let source = "~ \"open";
+        let mut scanner = Scanner::lossless(source);
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert_eq!(
+            get_token_types(&tokens),
+            vec![TokenType::Error, TokenType::Whitespace, TokenType::Error]
+        );
+        assert_eq!(tokens[2].lexeme, "\"open");
+        assert_eq!(errors.len(), 2, "错误仍然应该被报告");
+    }
+
+    #[test]
+    fn test_default_mode_has_no_trivia() {
+        let (tokens, _) = Scanner::new("a // c\n /* b */ ~ b").scan_tokens();
+
+        assert_eq!(get_token_types(&tokens), vec![TokenType::Identifier, TokenType::Identifier]);
+    }
+
+    // 辅助函数：简单的伪随机数生成器 (xorshift)，让属性测试可以复现
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    #[test]
+    fn property_lossless_round_trips_source() {
+        const FRAGMENTS: [&str; 36] = [
+            "var", "print", "x", "_y1", "123", "4.5", ".", " ", "  ", "\t", "\r", "\n",
+            "//", "///", "////", "/*", "*/", "/", "*", "\"", "\\", "\\n", "\\u{41}", "\\u{",
+            "(", ")", "{", "}", "=", "==", "!", "<=", ";", "~", "你好", "😀",
+        ];
+
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..2000 {
+            let len = rng.below(40);
+            let source: String = (0..len).map(|_| FRAGMENTS[rng.below(FRAGMENTS.len())]).collect();
+
+            let (tokens, _) = Scanner::lossless(&source).scan_tokens();
+            let rebuilt: String = tokens.iter().map(|t| t.lexeme).collect();
+            assert_eq!(rebuilt, source, "lossless 模式必须逐字节还原源代码");
+
+            let mut offset = 0;
+            for token in &tokens {
+                assert_eq!(token.span.start, offset, "token 之间不应该有空隙: {:?}", source);
+                assert!(!token.span.is_empty());
+                offset = token.span.end;
+            }
+        }
+    }
+}
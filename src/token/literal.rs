@@ -0,0 +1,14 @@
+/// Literal values carried by tokens
+///
+/// The scanner decodes literals once so that later stages never have to
+/// re-parse the lexeme.
+///
+/// # Variants
+///
+/// * `Number` - The value of a number literal
+/// * `String` - The decoded contents of a string literal, with escapes resolved
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Number(f64),
+    String(String),
+}
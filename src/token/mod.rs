@@ -1,25 +1,48 @@
-#![allow(unused)]
-
 /// Token type definitions
 mod types;
+/// Literal value definitions
+mod literal;
+/// Source span definitions
+mod span;
 
+pub use literal::Literal;
+pub use span::Span;
 pub use types::TokenType;
 
 /// Represents a single token in the Lox language
 /// 
+/// Tokens borrow their lexeme from the source they were scanned from, so
+/// producing one never copies the source text.
+/// 
+/// ```
+/// use rlox::{Scanner, Span, TokenType};
+/// 
+/// let source = "x = 10;";
+/// let token = Scanner::new(source).nth(2).unwrap().unwrap();
+/// 
+/// assert_eq!(token.token_type, TokenType::Number);
+/// assert_eq!(token.lexeme, "10");
+/// assert_eq!(token.span, Span::new(4, 6, 1, 5));
+/// assert_eq!(token.span.text(source), token.lexeme);
+/// ```
+/// 
 /// # Fields
 /// 
 /// * `token_type` - The category of the token
-/// * `lexeme` - The actual text of the token
-/// * `line` - The line number where the token appears
-#[derive(Debug)]
-pub struct Token {
+/// * `lexeme` - The actual text of the token, borrowed from the source
+/// * `literal` - The decoded value of a literal token, if any
+/// * `line` - The line number where the token ends, used for error reporting
+/// * `span` - Where the token's text sits in the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token<'src> {
     pub token_type: TokenType,
-    pub lexeme: String,
+    pub lexeme: &'src str,
+    pub literal: Option<Literal>,
     pub line: usize,
+    pub span: Span,
 }
 
-impl Token {
+impl<'src> Token<'src> {
     /// Creates a new Token instance
     /// 
     /// # Arguments
@@ -31,11 +54,83 @@ impl Token {
     /// # Returns
     /// 
     /// A new Token with the specified properties
-    pub fn new(token_type: TokenType, lexeme: &str, line: usize) -> Token {
+    pub fn new(token_type: TokenType, lexeme: &'src str, line: usize) -> Token<'src> {
+        Token {
+            token_type,
+            lexeme,
+            literal: None,
+            line,
+            span: Span::default(),
+        }
+    }
+
+    /// Creates a new Token carrying a literal value
+    /// 
+    /// # Arguments
+    /// 
+    /// * `token_type` - The type of the token
+    /// * `lexeme` - The raw source text of the token
+    /// * `literal` - The decoded value of the literal
+    /// * `line` - The line number where the token appears
+    /// 
+    /// # Returns
+    /// 
+    /// A new Token with the specified properties
+    pub fn with_literal(token_type: TokenType, lexeme: &'src str, literal: Literal, line: usize) -> Token<'src> {
         Token {
             token_type,
-            lexeme: String::from(lexeme),
+            lexeme,
+            literal: Some(literal),
             line,
+            span: Span::default(),
+        }
+    }
+
+    /// Attaches a source span to the token
+    /// 
+    /// # Arguments
+    /// 
+    /// * `span` - Where the token's text sits in the source
+    /// 
+    /// # Returns
+    /// 
+    /// The token with its span replaced
+    pub fn with_span(mut self, span: Span) -> Token<'src> {
+        self.span = span;
+        self
+    }
+
+    /// Makes an owned copy of the token
+    /// 
+    /// # Returns
+    /// 
+    /// An `OwnedToken` with the same type, lexeme, line and span
+    pub fn to_owned_token(&self) -> OwnedToken {
+        OwnedToken {
+            token_type: self.token_type,
+            lexeme: self.lexeme.to_string(),
+            line: self.line,
+            span: self.span,
         }
     }
-}
\ No newline at end of file
+}
+
+/// A token that owns its lexeme
+/// 
+/// Syntax trees outlive the source text they were parsed from (the REPL
+/// keeps declarations from earlier lines alive), so they hold tokens in
+/// this form instead of borrowing from the source.
+/// 
+/// # Fields
+/// 
+/// * `token_type` - The category of the token
+/// * `lexeme` - The actual text of the token
+/// * `line` - The line number where the token ends, used for error reporting
+/// * `span` - Where the token's text sat in the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwnedToken {
+    pub token_type: TokenType,
+    pub lexeme: String,
+    pub line: usize,
+    pub span: Span,
+}
@@ -0,0 +1,60 @@
+/// A region of the source code
+/// 
+/// # Fields
+/// 
+/// * `start` - Byte offset of the first byte in the region
+/// * `end` - Byte offset one past the last byte in the region
+/// * `line` - The 1-based line where the region starts
+/// * `column` - The 1-based column (in characters) where the region starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// Creates a new Span
+    /// 
+    /// # Arguments
+    /// 
+    /// * `start` - Byte offset where the region starts
+    /// * `end` - Byte offset where the region ends (exclusive)
+    /// * `line` - The line where the region starts
+    /// * `column` - The column where the region starts
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Span {
+        Span { start, end, line, column }
+    }
+
+    /// Returns the length of the region in bytes
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Checks whether the region is empty
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Returns the text this span covers
+    /// 
+    /// ```
+    /// use rlox::Span;
+    /// 
+    /// let span = Span::new(4, 9, 1, 5);
+    /// assert_eq!(span.text("var hello;"), "hello");
+    /// ```
+    /// 
+    /// # Arguments
+    /// 
+    /// * `source` - The source code the span was taken from
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the span is out of bounds for `source` or does not fall
+    /// on character boundaries, which means it came from a different source.
+    pub fn text<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.start..self.end]
+    }
+}
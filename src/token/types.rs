@@ -1,3 +1,5 @@
+use std::fmt;
+
 /// Enumerates all possible token types in the Lox language
 /// 
 /// # Variants
@@ -19,7 +21,29 @@
 /// 
 /// ## Keywords
 /// Standard language keywords like `If`, `While`, `Class`, etc.
-#[derive(Debug, Clone, PartialEq)]
+/// 
+/// ## Comments and Trivia
+/// * `DocComment` - A `///` documentation comment
+/// * `Comment`, `Whitespace` - Trivia, only produced by a lossless scanner
+/// * `Error` - Text that could not be scanned, only produced by a lossless scanner
+/// 
+/// New kinds may be added as the language grows, so matches outside this
+/// crate need a wildcard arm.
+/// 
+/// # Display
+/// 
+/// Token types with fixed spelling display as that spelling; the others
+/// display as a short description.
+/// 
+/// ```
+/// use rlox::TokenType;
+/// 
+/// assert_eq!(TokenType::BangEqual.to_string(), "!=");
+/// assert_eq!(TokenType::While.to_string(), "while");
+/// assert_eq!(TokenType::Identifier.to_string(), "identifier");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum TokenType {
     // single character tokens
     LeftParen, RightParen, LeftBrace, RightBrace,
@@ -37,5 +61,78 @@ pub enum TokenType {
     Nil, Or, Print, Return, Super, This,
     True, Var, While,
 
+    // comments and trivia
+    DocComment, Comment, Whitespace, Error,
+
     Eof,
-}
\ No newline at end of file
+}
+
+impl TokenType {
+    /// Checks whether tokens of this type carry no meaning for the grammar
+    /// 
+    /// Comments and whitespace are trivia; a parser can skip them.
+    /// 
+    /// ```
+    /// use rlox::TokenType;
+    /// 
+    /// assert!(TokenType::DocComment.is_trivia());
+    /// assert!(!TokenType::Identifier.is_trivia());
+    /// ```
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            TokenType::DocComment | TokenType::Comment | TokenType::Whitespace
+        )
+    }
+}
+
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            TokenType::LeftParen => "(",
+            TokenType::RightParen => ")",
+            TokenType::LeftBrace => "{",
+            TokenType::RightBrace => "}",
+            TokenType::Comma => ",",
+            TokenType::Dot => ".",
+            TokenType::Minus => "-",
+            TokenType::Plus => "+",
+            TokenType::Semicolon => ";",
+            TokenType::Slash => "/",
+            TokenType::Star => "*",
+            TokenType::Bang => "!",
+            TokenType::BangEqual => "!=",
+            TokenType::Equal => "=",
+            TokenType::EqualEqual => "==",
+            TokenType::Greater => ">",
+            TokenType::GreaterEqual => ">=",
+            TokenType::Less => "<",
+            TokenType::LessEqual => "<=",
+            TokenType::Identifier => "identifier",
+            TokenType::String => "string",
+            TokenType::Number => "number",
+            TokenType::And => "and",
+            TokenType::Class => "class",
+            TokenType::Else => "else",
+            TokenType::False => "false",
+            TokenType::Fun => "fun",
+            TokenType::For => "for",
+            TokenType::If => "if",
+            TokenType::Nil => "nil",
+            TokenType::Or => "or",
+            TokenType::Print => "print",
+            TokenType::Return => "return",
+            TokenType::Super => "super",
+            TokenType::This => "this",
+            TokenType::True => "true",
+            TokenType::Var => "var",
+            TokenType::While => "while",
+            TokenType::DocComment => "doc comment",
+            TokenType::Comment => "comment",
+            TokenType::Whitespace => "whitespace",
+            TokenType::Error => "error",
+            TokenType::Eof => "end of file",
+        };
+        f.write_str(text)
+    }
+}
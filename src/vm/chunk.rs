@@ -0,0 +1,296 @@
+use std::fmt;
+
+use super::Value;
+
+/// Instructions of the bytecode virtual machine
+/// 
+/// Each instruction is one byte, followed by its operands. Operands are
+/// single bytes unless noted otherwise.
+/// 
+/// # Variants
+/// 
+/// * `Constant` - Push the constant at the operand's index
+/// * `Nil`, `True`, `False` - Push a literal
+/// * `Pop` - Discard the top of the stack
+/// * `GetLocal`, `SetLocal` - Read or write the stack slot at the operand
+/// * `GetGlobal`, `DefineGlobal`, `SetGlobal` - Read, define or write the
+///   global named by the constant at the operand
+/// * `GetUpvalue`, `SetUpvalue` - Read or write the current closure's
+///   upvalue at the operand
+/// * `GetProperty`, `SetProperty` - Read or write the property named by
+///   the constant at the operand
+/// * `GetSuper` - Bind the superclass method named by the constant at the
+///   operand to the receiver below the superclass
+/// * `Equal`, `Greater`, `Less` - Compare the top two values
+/// * `Add`, `Subtract`, `Multiply`, `Divide` - Arithmetic on the top two values
+/// * `Not`, `Negate` - Unary operators
+/// * `Print` - Pop and print the top of the stack
+/// * `Jump`, `JumpIfFalse` - Jump forward by the two-byte operand
+/// * `Loop` - Jump backward by the two-byte operand
+/// * `Call` - Call the value below the operand's number of arguments
+/// * `Invoke` - Call the method named by the first operand with the
+///   second operand's number of arguments
+/// * `SuperInvoke` - Like `Invoke`, but looks the method up in the
+///   superclass on top of the stack
+/// * `Closure` - Wrap the function constant at the first operand in a
+///   closure; a pair of bytes follows for each upvalue, saying whether it
+///   captures a local of the enclosing function and which slot or upvalue
+/// * `CloseUpvalue` - Move the local on top of the stack into the upvalues
+///   that capture it, then pop it
+/// * `Return` - Return from the current function
+/// * `Class` - Push a new class named by the constant at the operand
+/// * `Inherit` - Copy the superclass's methods into the subclass
+/// * `Method` - Add the closure on top of the stack to the class below it
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpCode {
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal,
+    SetLocal,
+    GetGlobal,
+    DefineGlobal,
+    SetGlobal,
+    GetUpvalue,
+    SetUpvalue,
+    GetProperty,
+    SetProperty,
+    GetSuper,
+    Equal,
+    Greater,
+    Less,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    Jump,
+    JumpIfFalse,
+    Loop,
+    Call,
+    Invoke,
+    SuperInvoke,
+    Closure,
+    CloseUpvalue,
+    Return,
+    Class,
+    Inherit,
+    Method,
+}
+
+impl OpCode {
+    /// Every instruction, indexed by its byte
+    const ALL: [OpCode; 37] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::GetGlobal,
+        OpCode::DefineGlobal,
+        OpCode::SetGlobal,
+        OpCode::GetUpvalue,
+        OpCode::SetUpvalue,
+        OpCode::GetProperty,
+        OpCode::SetProperty,
+        OpCode::GetSuper,
+        OpCode::Equal,
+        OpCode::Greater,
+        OpCode::Less,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::Loop,
+        OpCode::Call,
+        OpCode::Invoke,
+        OpCode::SuperInvoke,
+        OpCode::Closure,
+        OpCode::CloseUpvalue,
+        OpCode::Return,
+        OpCode::Class,
+        OpCode::Inherit,
+        OpCode::Method,
+    ];
+
+    /// Decodes an instruction byte
+    /// 
+    /// # Returns
+    /// 
+    /// The instruction, or `None` if the byte is not a valid instruction
+    pub fn from_byte(byte: u8) -> Option<OpCode> {
+        OpCode::ALL.get(byte as usize).copied()
+    }
+}
+
+impl fmt::Display for OpCode {
+    /// The instruction's name in listings, e.g. `OP_GET_LOCAL`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            OpCode::Constant => "OP_CONSTANT",
+            OpCode::Nil => "OP_NIL",
+            OpCode::True => "OP_TRUE",
+            OpCode::False => "OP_FALSE",
+            OpCode::Pop => "OP_POP",
+            OpCode::GetLocal => "OP_GET_LOCAL",
+            OpCode::SetLocal => "OP_SET_LOCAL",
+            OpCode::GetGlobal => "OP_GET_GLOBAL",
+            OpCode::DefineGlobal => "OP_DEFINE_GLOBAL",
+            OpCode::SetGlobal => "OP_SET_GLOBAL",
+            OpCode::GetUpvalue => "OP_GET_UPVALUE",
+            OpCode::SetUpvalue => "OP_SET_UPVALUE",
+            OpCode::GetProperty => "OP_GET_PROPERTY",
+            OpCode::SetProperty => "OP_SET_PROPERTY",
+            OpCode::GetSuper => "OP_GET_SUPER",
+            OpCode::Equal => "OP_EQUAL",
+            OpCode::Greater => "OP_GREATER",
+            OpCode::Less => "OP_LESS",
+            OpCode::Add => "OP_ADD",
+            OpCode::Subtract => "OP_SUBTRACT",
+            OpCode::Multiply => "OP_MULTIPLY",
+            OpCode::Divide => "OP_DIVIDE",
+            OpCode::Not => "OP_NOT",
+            OpCode::Negate => "OP_NEGATE",
+            OpCode::Print => "OP_PRINT",
+            OpCode::Jump => "OP_JUMP",
+            OpCode::JumpIfFalse => "OP_JUMP_IF_FALSE",
+            OpCode::Loop => "OP_LOOP",
+            OpCode::Call => "OP_CALL",
+            OpCode::Invoke => "OP_INVOKE",
+            OpCode::SuperInvoke => "OP_SUPER_INVOKE",
+            OpCode::Closure => "OP_CLOSURE",
+            OpCode::CloseUpvalue => "OP_CLOSE_UPVALUE",
+            OpCode::Return => "OP_RETURN",
+            OpCode::Class => "OP_CLASS",
+            OpCode::Inherit => "OP_INHERIT",
+            OpCode::Method => "OP_METHOD",
+        };
+        f.pad(name)
+    }
+}
+
+/// The start of a run of bytes compiled from the same source line
+/// 
+/// # Fields
+/// 
+/// * `offset` - The first byte of the run
+/// * `line` - The source line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LineStart {
+    offset: usize,
+    line: usize,
+}
+
+/// A sequence of bytecode with its constants and line information
+/// 
+/// Lines are stored run-length encoded: a new entry is only added when
+/// an instruction comes from a different line than the one before it.
+/// 
+/// # Fields
+/// 
+/// * `code` - The instructions and their operands
+/// * `constants` - The constant pool that `Constant` and friends index
+/// * `lines` - The line table
+#[derive(Debug, Clone, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    lines: Vec<LineStart>,
+}
+
+impl Chunk {
+    /// Creates an empty chunk
+    pub fn new() -> Chunk {
+        Chunk::default()
+    }
+
+    /// Appends a byte
+    /// 
+    /// # Arguments
+    /// 
+    /// * `byte` - An instruction or operand
+    /// * `line` - The source line the byte was compiled from
+    pub fn write(&mut self, byte: u8, line: usize) {
+        if self.lines.last().map(|start| start.line) != Some(line) {
+            self.lines.push(LineStart {
+                offset: self.code.len(),
+                line,
+            });
+        }
+        self.code.push(byte);
+    }
+
+    /// Adds a value to the constant pool
+    /// 
+    /// # Returns
+    /// 
+    /// The index of the constant
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    /// Returns the source line of the byte at `offset`
+    pub fn line(&self, offset: usize) -> usize {
+        let run = self.lines.partition_point(|start| start.offset <= offset);
+        run.checked_sub(1).map_or(0, |run| self.lines[run].line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opcode_round_trips_through_bytes() {
+        for (byte, op) in OpCode::ALL.iter().enumerate() {
+            assert_eq!(*op as u8 as usize, byte);
+            assert_eq!(OpCode::from_byte(byte as u8), Some(*op));
+        }
+        assert_eq!(OpCode::from_byte(OpCode::ALL.len() as u8), None);
+    }
+
+    #[test]
+    fn test_opcode_names() {
+        assert_eq!(OpCode::Constant.to_string(), "OP_CONSTANT");
+        assert_eq!(OpCode::JumpIfFalse.to_string(), "OP_JUMP_IF_FALSE");
+        // 名称支持宽度对齐，方便反汇编输出
+        assert_eq!(format!("{:<10}|", OpCode::Nil), "OP_NIL    |");
+    }
+
+    #[test]
+    fn test_line_table() {
+        let mut chunk = Chunk::new();
+        chunk.write(OpCode::Nil as u8, 1);
+        chunk.write(OpCode::Print as u8, 1);
+        chunk.write(OpCode::True as u8, 3);
+        chunk.write(OpCode::Constant as u8, 4);
+        chunk.write(0, 4);
+        chunk.write(OpCode::Return as u8, 4);
+
+        let lines: Vec<usize> = (0..chunk.code.len()).map(|offset| chunk.line(offset)).collect();
+        assert_eq!(lines, [1, 1, 3, 4, 4, 4]);
+        // 行号表只在行号变化时增加条目
+        assert_eq!(chunk.lines.len(), 3);
+    }
+
+    #[test]
+    fn test_add_constant() {
+        let mut chunk = Chunk::new();
+        assert_eq!(chunk.add_constant(Value::Number(1.0)), 0);
+        assert_eq!(chunk.add_constant(Value::Nil), 1);
+        assert_eq!(chunk.constants.len(), 2);
+    }
+}
@@ -0,0 +1,1313 @@
+use std::{error::Error, fmt};
+
+use super::{Chunk, Heap, Obj, ObjFunction, ObjRef, OpCode, Value};
+use crate::{
+    scanner::{ScanError, Scanner},
+    token::{Literal, Token, TokenType},
+};
+
+/// The most local variables a function may have, including its parameters
+const MAX_LOCALS: usize = 256;
+/// The most constants a chunk may have
+const MAX_CONSTANTS: usize = 256;
+/// The most variables a function may capture from enclosing functions
+const MAX_UPVALUES: usize = 256;
+/// The most arguments a call, or parameters a function, may have
+const MAX_ARITY: usize = 255;
+
+/// An error found while compiling
+/// 
+/// # Fields
+/// 
+/// * `line` - The line where the error was found
+/// * `location` - Where on the line, e.g. ` at 'x'` or ` at end`
+/// * `message` - A description of the error
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    pub line: usize,
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] Error{}: {}", self.line, self.location, self.message)
+    }
+}
+
+impl Error for CompileError {}
+
+/// Compiles a program to bytecode
+/// 
+/// Reads tokens straight from a [`Scanner`] and emits bytecode as it
+/// parses, without building a syntax tree.
+/// 
+/// # Arguments
+/// 
+/// * `source` - The program's source code
+/// * `heap` - Where the compiled functions and their constants are allocated
+/// 
+/// # Returns
+/// 
+/// The function holding the program's top-level code, or every error found
+pub fn compile(source: &str, heap: &mut Heap) -> Result<ObjRef, Vec<CompileError>> {
+    compile_with_roots(source, heap, &[])
+}
+
+/// Compiles a program on a heap that holds other live objects
+/// 
+/// The compiler may collect garbage while it allocates; `roots` keeps the
+/// caller's objects alive through those collections.
+pub(super) fn compile_with_roots(
+    source: &str,
+    heap: &mut Heap,
+    roots: &[Value],
+) -> Result<ObjRef, Vec<CompileError>> {
+    let mut compiler = Compiler::new(source, heap, roots);
+    compiler.advance();
+    while !compiler.match_(TokenType::Eof) {
+        compiler.declaration();
+    }
+    let (function, _) = compiler.end_function();
+
+    if compiler.errors.is_empty() {
+        Ok(compiler.heap.alloc(Obj::Function(function)))
+    } else {
+        Err(compiler.errors)
+    }
+}
+
+/// Operator precedence, from lowest to highest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    None,
+    Assignment,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    Unary,
+    Call,
+    Primary,
+}
+
+impl Precedence {
+    /// the next higher precedence, for left-associative operators
+    fn next(self) -> Precedence {
+        match self {
+            Precedence::None => Precedence::Assignment,
+            Precedence::Assignment => Precedence::Or,
+            Precedence::Or => Precedence::And,
+            Precedence::And => Precedence::Equality,
+            Precedence::Equality => Precedence::Comparison,
+            Precedence::Comparison => Precedence::Term,
+            Precedence::Term => Precedence::Factor,
+            Precedence::Factor => Precedence::Unary,
+            Precedence::Unary => Precedence::Call,
+            Precedence::Call | Precedence::Primary => Precedence::Primary,
+        }
+    }
+
+    /// the precedence of a token used as an infix operator
+    fn of_infix(token_type: TokenType) -> Precedence {
+        match token_type {
+            TokenType::LeftParen | TokenType::Dot => Precedence::Call,
+            TokenType::Star | TokenType::Slash => Precedence::Factor,
+            TokenType::Minus | TokenType::Plus => Precedence::Term,
+            TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual => {
+                Precedence::Comparison
+            }
+            TokenType::BangEqual | TokenType::EqualEqual => Precedence::Equality,
+            TokenType::And => Precedence::And,
+            TokenType::Or => Precedence::Or,
+            _ => Precedence::None,
+        }
+    }
+}
+
+/// The kind of function being compiled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FunctionKind {
+    Function,
+    Initializer,
+    Method,
+    Script,
+}
+
+/// A local variable in the function being compiled
+/// 
+/// # Fields
+/// 
+/// * `name` - The variable's name
+/// * `depth` - The scope depth it was declared at, or `None` while its
+///   initializer is being compiled
+/// * `is_captured` - Whether a closure captures it, so it must be moved
+///   into an upvalue when it goes out of scope
+#[derive(Debug)]
+struct Local<'src> {
+    name: &'src str,
+    depth: Option<usize>,
+    is_captured: bool,
+}
+
+/// A variable captured by the function being compiled
+/// 
+/// # Fields
+/// 
+/// * `index` - The local slot, or upvalue index, in the enclosing function
+/// * `is_local` - Whether it is a local of the enclosing function rather
+///   than one of its upvalues
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Upvalue {
+    index: u8,
+    is_local: bool,
+}
+
+/// The state of one function being compiled
+/// 
+/// # Fields
+/// 
+/// * `function` - The function being built
+/// * `kind` - The kind of function
+/// * `locals` - The local variables in scope, by stack slot
+/// * `upvalues` - The variables captured from enclosing functions
+/// * `scope_depth` - How many blocks deep the compiler is
+#[derive(Debug)]
+struct FunctionState<'src> {
+    function: ObjFunction,
+    kind: FunctionKind,
+    locals: Vec<Local<'src>>,
+    upvalues: Vec<Upvalue>,
+    scope_depth: usize,
+}
+
+impl<'src> FunctionState<'src> {
+    fn new(kind: FunctionKind, name: Option<ObjRef>) -> FunctionState<'src> {
+        // slot 0 holds the function itself, or the receiver in methods
+        let receiver = match kind {
+            FunctionKind::Initializer | FunctionKind::Method => "this",
+            FunctionKind::Function | FunctionKind::Script => "",
+        };
+        FunctionState {
+            function: ObjFunction {
+                name,
+                ..ObjFunction::default()
+            },
+            kind,
+            locals: vec![Local {
+                name: receiver,
+                depth: Some(0),
+                is_captured: false,
+            }],
+            upvalues: Vec::new(),
+            scope_depth: 0,
+        }
+    }
+}
+
+/// The state of one class being compiled
+/// 
+/// # Fields
+/// 
+/// * `has_superclass` - Whether the class inherits from another
+#[derive(Debug)]
+struct ClassState {
+    has_superclass: bool,
+}
+
+/// Single-pass compiler from tokens to bytecode
+/// 
+/// A Pratt parser for expressions and recursive descent for statements,
+/// emitting instructions as each construct is recognized.
+/// 
+/// # Fields
+/// 
+/// * `scanner` - Where tokens come from
+/// * `current` - The next token to consume
+/// * `previous` - The token consumed last
+/// * `panic_mode` - Whether errors are suppressed until the next statement
+/// * `errors` - The errors found so far
+/// * `heap` - Where functions and constants are allocated
+/// * `roots` - Objects the caller holds, kept alive by collections
+/// * `functions` - The functions being compiled, innermost last
+/// * `classes` - The classes being compiled, innermost last
+struct Compiler<'src, 'heap> {
+    scanner: Scanner<'src>,
+    current: Token<'src>,
+    previous: Token<'src>,
+    panic_mode: bool,
+    errors: Vec<CompileError>,
+    heap: &'heap mut Heap,
+    roots: &'heap [Value],
+    functions: Vec<FunctionState<'src>>,
+    classes: Vec<ClassState>,
+}
+
+impl<'src, 'heap> Compiler<'src, 'heap> {
+    fn new(source: &'src str, heap: &'heap mut Heap, roots: &'heap [Value]) -> Compiler<'src, 'heap> {
+        Compiler {
+            scanner: Scanner::new(source),
+            current: Token::new(TokenType::Eof, "", 1),
+            previous: Token::new(TokenType::Eof, "", 1),
+            panic_mode: false,
+            errors: Vec::new(),
+            heap,
+            roots,
+            functions: vec![FunctionState::new(FunctionKind::Script, None)],
+            classes: Vec::new(),
+        }
+    }
+
+    // ---- declarations ----
+
+    /// declaration → classDecl | funDecl | varDecl | statement
+    fn declaration(&mut self) {
+        if self.match_(TokenType::Class) {
+            self.class_declaration();
+        } else if self.match_(TokenType::Fun) {
+            self.fun_declaration();
+        } else if self.match_(TokenType::Var) {
+            self.var_declaration();
+        } else {
+            self.statement();
+        }
+
+        if self.panic_mode {
+            self.synchronize();
+        }
+    }
+
+    /// classDecl → "class" IDENTIFIER ( "<" IDENTIFIER )? "{" function* "}"
+    fn class_declaration(&mut self) {
+        self.consume(TokenType::Identifier, "Expect class name.");
+        let class_name = self.previous.lexeme;
+        let name_constant = self.identifier_constant(class_name);
+        self.declare_variable();
+
+        self.emit_op_with(OpCode::Class, name_constant);
+        self.define_variable(name_constant);
+
+        self.classes.push(ClassState { has_superclass: false });
+
+        if self.match_(TokenType::Less) {
+            self.consume(TokenType::Identifier, "Expect superclass name.");
+            self.variable(false);
+            if self.previous.lexeme == class_name {
+                self.error("A class can't inherit from itself.");
+            }
+
+            // the superclass stays on the stack as a hidden local
+            self.begin_scope();
+            self.add_local("super");
+            self.define_variable(0);
+
+            self.named_variable(class_name, false);
+            self.emit_op(OpCode::Inherit);
+            self.current_class().has_superclass = true;
+        }
+
+        // keep the class on the stack while its methods are bound to it
+        self.named_variable(class_name, false);
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.");
+        while !self.check(TokenType::RightBrace) && !self.check(TokenType::Eof) {
+            self.method();
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after class body.");
+        self.emit_op(OpCode::Pop);
+
+        if self.current_class().has_superclass {
+            self.end_scope();
+        }
+        self.classes.pop();
+    }
+
+    /// a method inside a class body
+    fn method(&mut self) {
+        self.consume(TokenType::Identifier, "Expect method name.");
+        let constant = self.identifier_constant(self.previous.lexeme);
+        let kind = if self.previous.lexeme == "init" {
+            FunctionKind::Initializer
+        } else {
+            FunctionKind::Method
+        };
+        self.function(kind);
+        self.emit_op_with(OpCode::Method, constant);
+    }
+
+    /// funDecl → "fun" function
+    fn fun_declaration(&mut self) {
+        let global = self.parse_variable("Expect function name.");
+        // a function may refer to itself, so it is usable before its body
+        self.mark_initialized();
+        self.function(FunctionKind::Function);
+        self.define_variable(global);
+    }
+
+    /// function → IDENTIFIER "(" parameters? ")" block
+    /// 
+    /// The name has already been consumed. Compiles the body into a new
+    /// function and emits a closure over it in the enclosing one.
+    fn function(&mut self, kind: FunctionKind) {
+        let noun = match kind {
+            FunctionKind::Initializer | FunctionKind::Method => "method",
+            FunctionKind::Function | FunctionKind::Script => "function",
+        };
+        let name = self.intern(self.previous.lexeme);
+        self.functions.push(FunctionState::new(kind, Some(name)));
+        self.begin_scope();
+
+        self.consume(TokenType::LeftParen, &format!("Expect '(' after {} name.", noun));
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if self.current_function().function.arity == MAX_ARITY {
+                    self.error_at_current("Can't have more than 255 parameters.");
+                }
+                self.current_function().function.arity += 1;
+                let constant = self.parse_variable("Expect parameter name.");
+                self.define_variable(constant);
+                if !self.match_(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after parameters.");
+        self.consume(TokenType::LeftBrace, &format!("Expect '{{' before {} body.", noun));
+        self.block();
+
+        let (function, upvalues) = self.end_function();
+        let function = self.heap.alloc(Obj::Function(function));
+        let constant = self.make_constant(Value::Obj(function));
+        self.emit_op_with(OpCode::Closure, constant);
+        for upvalue in upvalues {
+            self.emit_byte(upvalue.is_local as u8);
+            self.emit_byte(upvalue.index);
+        }
+    }
+
+    /// varDecl → "var" IDENTIFIER ( "=" expression )? ";"
+    fn var_declaration(&mut self) {
+        let global = self.parse_variable("Expect variable name.");
+
+        if self.match_(TokenType::Equal) {
+            self.expression();
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+        self.consume(TokenType::Semicolon, "Expect ';' after variable declaration.");
+
+        self.define_variable(global);
+    }
+
+    // ---- statements ----
+
+    /// statement → exprStmt | forStmt | ifStmt | printStmt | returnStmt | whileStmt | block
+    fn statement(&mut self) {
+        if self.match_(TokenType::Print) {
+            self.print_statement();
+        } else if self.match_(TokenType::For) {
+            self.for_statement();
+        } else if self.match_(TokenType::If) {
+            self.if_statement();
+        } else if self.match_(TokenType::Return) {
+            self.return_statement();
+        } else if self.match_(TokenType::While) {
+            self.while_statement();
+        } else if self.match_(TokenType::LeftBrace) {
+            self.begin_scope();
+            self.block();
+            self.end_scope();
+        } else {
+            self.expression_statement();
+        }
+    }
+
+    /// block → "{" declaration* "}", with the opening brace consumed
+    fn block(&mut self) {
+        while !self.check(TokenType::RightBrace) && !self.check(TokenType::Eof) {
+            self.declaration();
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after block.");
+    }
+
+    /// printStmt → "print" expression ";"
+    fn print_statement(&mut self) {
+        self.expression();
+        self.consume(TokenType::Semicolon, "Expect ';' after value.");
+        self.emit_op(OpCode::Print);
+    }
+
+    /// exprStmt → expression ";"
+    fn expression_statement(&mut self) {
+        self.expression();
+        self.consume(TokenType::Semicolon, "Expect ';' after expression.");
+        self.emit_op(OpCode::Pop);
+    }
+
+    /// forStmt → "for" "(" ( varDecl | exprStmt | ";" ) expression? ";" expression? ")" statement
+    /// 
+    /// The increment is compiled before the body, so the body jumps over
+    /// it on the way in and loops back to it at the end.
+    fn for_statement(&mut self) {
+        self.begin_scope();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'.");
+        if self.match_(TokenType::Semicolon) {
+            // no initializer
+        } else if self.match_(TokenType::Var) {
+            self.var_declaration();
+        } else {
+            self.expression_statement();
+        }
+
+        let mut loop_start = self.current_chunk().code.len();
+        let mut exit_jump = None;
+        if !self.match_(TokenType::Semicolon) {
+            self.expression();
+            self.consume(TokenType::Semicolon, "Expect ';' after loop condition.");
+            exit_jump = Some(self.emit_jump(OpCode::JumpIfFalse));
+            self.emit_op(OpCode::Pop);
+        }
+
+        if !self.match_(TokenType::RightParen) {
+            let body_jump = self.emit_jump(OpCode::Jump);
+            let increment_start = self.current_chunk().code.len();
+            self.expression();
+            self.emit_op(OpCode::Pop);
+            self.consume(TokenType::RightParen, "Expect ')' after for clauses.");
+
+            self.emit_loop(loop_start);
+            loop_start = increment_start;
+            self.patch_jump(body_jump);
+        }
+
+        self.statement();
+        self.emit_loop(loop_start);
+
+        if let Some(exit_jump) = exit_jump {
+            self.patch_jump(exit_jump);
+            self.emit_op(OpCode::Pop);
+        }
+        self.end_scope();
+    }
+
+    /// ifStmt → "if" "(" expression ")" statement ( "else" statement )?
+    fn if_statement(&mut self) {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.");
+        self.expression();
+        self.consume(TokenType::RightParen, "Expect ')' after if condition.");
+
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        self.statement();
+        let else_jump = self.emit_jump(OpCode::Jump);
+
+        self.patch_jump(then_jump);
+        self.emit_op(OpCode::Pop);
+        if self.match_(TokenType::Else) {
+            self.statement();
+        }
+        self.patch_jump(else_jump);
+    }
+
+    /// returnStmt → "return" expression? ";"
+    fn return_statement(&mut self) {
+        if self.current_function().kind == FunctionKind::Script {
+            self.error("Can't return from top-level code.");
+        }
+
+        if self.match_(TokenType::Semicolon) {
+            self.emit_return();
+        } else {
+            if self.current_function().kind == FunctionKind::Initializer {
+                self.error("Can't return a value from an initializer.");
+            }
+            self.expression();
+            self.consume(TokenType::Semicolon, "Expect ';' after return value.");
+            self.emit_op(OpCode::Return);
+        }
+    }
+
+    /// whileStmt → "while" "(" expression ")" statement
+    fn while_statement(&mut self) {
+        let loop_start = self.current_chunk().code.len();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.");
+        self.expression();
+        self.consume(TokenType::RightParen, "Expect ')' after condition.");
+
+        let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        self.statement();
+        self.emit_loop(loop_start);
+
+        self.patch_jump(exit_jump);
+        self.emit_op(OpCode::Pop);
+    }
+
+    /// skip tokens until a likely statement boundary, leaving panic mode
+    fn synchronize(&mut self) {
+        self.panic_mode = false;
+
+        while self.current.token_type != TokenType::Eof {
+            if self.previous.token_type == TokenType::Semicolon {
+                return;
+            }
+            match self.current.token_type {
+                TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return => return,
+                _ => self.advance(),
+            }
+        }
+    }
+
+    // ---- expressions ----
+
+    /// expression → assignment
+    fn expression(&mut self) {
+        self.parse_precedence(Precedence::Assignment);
+    }
+
+    /// parse an expression whose operators bind at least as tightly as `precedence`
+    fn parse_precedence(&mut self, precedence: Precedence) {
+        self.advance();
+        let can_assign = precedence <= Precedence::Assignment;
+        if !self.prefix(self.previous.token_type, can_assign) {
+            self.error("Expect expression.");
+            return;
+        }
+
+        while precedence <= Precedence::of_infix(self.current.token_type) {
+            self.advance();
+            self.infix(self.previous.token_type, can_assign);
+        }
+
+        if can_assign && self.match_(TokenType::Equal) {
+            self.error("Invalid assignment target.");
+        }
+    }
+
+    /// compile the expression that starts with the token just consumed
+    /// 
+    /// Returns `false` if no expression starts with that token.
+    fn prefix(&mut self, token_type: TokenType, can_assign: bool) -> bool {
+        match token_type {
+            TokenType::LeftParen => self.grouping(),
+            TokenType::Minus | TokenType::Bang => self.unary(),
+            TokenType::Number | TokenType::String => self.literal_value(),
+            TokenType::Nil => self.emit_op(OpCode::Nil),
+            TokenType::True => self.emit_op(OpCode::True),
+            TokenType::False => self.emit_op(OpCode::False),
+            TokenType::Identifier => self.variable(can_assign),
+            TokenType::This => self.this(),
+            TokenType::Super => self.super_(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// compile the rest of an infix expression whose operator was just consumed
+    fn infix(&mut self, token_type: TokenType, can_assign: bool) {
+        match token_type {
+            TokenType::LeftParen => self.call(),
+            TokenType::Dot => self.dot(can_assign),
+            TokenType::And => self.and(),
+            TokenType::Or => self.or(),
+            _ => self.binary(),
+        }
+    }
+
+    /// "(" expression ")"
+    fn grouping(&mut self) {
+        self.expression();
+        self.consume(TokenType::RightParen, "Expect ')' after expression.");
+    }
+
+    /// ( "!" | "-" ) unary
+    fn unary(&mut self) {
+        let operator = self.previous.token_type;
+        let line = self.previous.line;
+        self.parse_precedence(Precedence::Unary);
+
+        match operator {
+            TokenType::Bang => self.emit_op_at(OpCode::Not, line),
+            _ => self.emit_op_at(OpCode::Negate, line),
+        }
+    }
+
+    /// an arithmetic, comparison or equality operator and its right operand
+    /// 
+    /// The instructions carry the operator's line, so errors point at it.
+    fn binary(&mut self) {
+        let operator = self.previous.token_type;
+        let line = self.previous.line;
+        self.parse_precedence(Precedence::of_infix(operator).next());
+
+        let (op, negate) = match operator {
+            TokenType::BangEqual => (OpCode::Equal, true),
+            TokenType::EqualEqual => (OpCode::Equal, false),
+            TokenType::Greater => (OpCode::Greater, false),
+            TokenType::GreaterEqual => (OpCode::Less, true),
+            TokenType::Less => (OpCode::Less, false),
+            TokenType::LessEqual => (OpCode::Greater, true),
+            TokenType::Plus => (OpCode::Add, false),
+            TokenType::Minus => (OpCode::Subtract, false),
+            TokenType::Star => (OpCode::Multiply, false),
+            _ => (OpCode::Divide, false),
+        };
+        self.emit_op_at(op, line);
+        if negate {
+            self.emit_op_at(OpCode::Not, line);
+        }
+    }
+
+    /// the right operand of `and`, skipped if the left one is falsey
+    fn and(&mut self) {
+        let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        self.parse_precedence(Precedence::And);
+        self.patch_jump(end_jump);
+    }
+
+    /// the right operand of `or`, skipped if the left one is truthy
+    fn or(&mut self) {
+        let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+        let end_jump = self.emit_jump(OpCode::Jump);
+        self.patch_jump(else_jump);
+        self.emit_op(OpCode::Pop);
+        self.parse_precedence(Precedence::Or);
+        self.patch_jump(end_jump);
+    }
+
+    /// a number or string literal
+    fn literal_value(&mut self) {
+        let value = match self.previous.literal.clone() {
+            Some(Literal::Number(n)) => Value::Number(n),
+            Some(Literal::String(s)) => Value::Obj(self.intern(&s)),
+            None => Value::Nil,
+        };
+        let constant = self.make_constant(value);
+        self.emit_op_with(OpCode::Constant, constant);
+    }
+
+    /// a call's argument list, with the opening parenthesis consumed
+    fn call(&mut self) {
+        let arg_count = self.argument_list();
+        self.emit_op_with(OpCode::Call, arg_count);
+    }
+
+    /// a property access, assignment or method call after "."
+    fn dot(&mut self, can_assign: bool) {
+        self.consume(TokenType::Identifier, "Expect property name after '.'.");
+        let name = self.identifier_constant(self.previous.lexeme);
+
+        if can_assign && self.match_(TokenType::Equal) {
+            self.expression();
+            self.emit_op_with(OpCode::SetProperty, name);
+        } else if self.match_(TokenType::LeftParen) {
+            let arg_count = self.argument_list();
+            self.emit_op_with(OpCode::Invoke, name);
+            self.emit_byte(arg_count);
+        } else {
+            self.emit_op_with(OpCode::GetProperty, name);
+        }
+    }
+
+    /// arguments → expression ( "," expression )*, up to the closing parenthesis
+    fn argument_list(&mut self) -> u8 {
+        let mut count = 0;
+        if !self.check(TokenType::RightParen) {
+            loop {
+                self.expression();
+                if count == MAX_ARITY {
+                    self.error("Can't have more than 255 arguments.");
+                }
+                count += 1;
+                if !self.match_(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after arguments.");
+        count.min(MAX_ARITY) as u8
+    }
+
+    /// an identifier used as a variable
+    fn variable(&mut self, can_assign: bool) {
+        self.named_variable(self.previous.lexeme, can_assign);
+    }
+
+    /// the `this` keyword, which is the receiver's local slot
+    fn this(&mut self) {
+        if self.classes.is_empty() {
+            self.error("Can't use 'this' outside of a class.");
+            return;
+        }
+        self.variable(false);
+    }
+
+    /// "super" "." IDENTIFIER
+    fn super_(&mut self) {
+        match self.classes.last() {
+            None => self.error("Can't use 'super' outside of a class."),
+            Some(class) if !class.has_superclass => {
+                self.error("Can't use 'super' in a class with no superclass.")
+            }
+            Some(_) => (),
+        }
+        self.consume(TokenType::Dot, "Expect '.' after 'super'.");
+        self.consume(TokenType::Identifier, "Expect superclass method name.");
+        let name = self.identifier_constant(self.previous.lexeme);
+
+        // the receiver goes below the superclass, which is captured from
+        // the scope around the class's methods
+        self.named_variable("this", false);
+        if self.match_(TokenType::LeftParen) {
+            let arg_count = self.argument_list();
+            self.named_variable("super", false);
+            self.emit_op_with(OpCode::SuperInvoke, name);
+            self.emit_byte(arg_count);
+        } else {
+            self.named_variable("super", false);
+            self.emit_op_with(OpCode::GetSuper, name);
+        }
+    }
+
+    /// load or store a variable: a local of the current function, one
+    /// captured from an enclosing function, or a global
+    fn named_variable(&mut self, name: &'src str, can_assign: bool) {
+        let current = self.functions.len() - 1;
+        let (get, set, operand) = if let Some(slot) = self.resolve_local(current, name) {
+            (OpCode::GetLocal, OpCode::SetLocal, slot)
+        } else if let Some(index) = self.resolve_upvalue(current, name) {
+            (OpCode::GetUpvalue, OpCode::SetUpvalue, index)
+        } else {
+            (OpCode::GetGlobal, OpCode::SetGlobal, self.identifier_constant(name))
+        };
+
+        if can_assign && self.match_(TokenType::Equal) {
+            self.expression();
+            self.emit_op_with(set, operand);
+        } else {
+            self.emit_op_with(get, operand);
+        }
+    }
+
+    // ---- variables and scopes ----
+
+    /// find a local variable of the function at `function` by name
+    fn resolve_local(&mut self, function: usize, name: &str) -> Option<u8> {
+        let locals = &self.functions[function].locals;
+        let (slot, local) = locals.iter().enumerate().rev().find(|(_, local)| local.name == name)?;
+        if local.depth.is_none() {
+            self.error("Can't read local variable in its own initializer.");
+        }
+        Some(slot as u8)
+    }
+
+    /// find a variable of an enclosing function and capture it into the
+    /// function at `function`, through every function in between
+    /// 
+    /// Returns the index of the upvalue in the function at `function`.
+    fn resolve_upvalue(&mut self, function: usize, name: &str) -> Option<u8> {
+        let enclosing = function.checked_sub(1)?;
+
+        if let Some(slot) = self.resolve_local(enclosing, name) {
+            self.functions[enclosing].locals[slot as usize].is_captured = true;
+            return Some(self.add_upvalue(function, slot, true));
+        }
+        let index = self.resolve_upvalue(enclosing, name)?;
+        Some(self.add_upvalue(function, index, false))
+    }
+
+    /// add an upvalue to the function at `function`, reusing an existing
+    /// one that captures the same variable
+    fn add_upvalue(&mut self, function: usize, index: u8, is_local: bool) -> u8 {
+        let upvalue = Upvalue { index, is_local };
+        let upvalues = &self.functions[function].upvalues;
+        if let Some(existing) = upvalues.iter().position(|&captured| captured == upvalue) {
+            return existing as u8;
+        }
+
+        if upvalues.len() == MAX_UPVALUES {
+            self.error("Too many closure variables in function.");
+            return 0;
+        }
+        let state = &mut self.functions[function];
+        state.upvalues.push(upvalue);
+        state.function.upvalue_count = state.upvalues.len();
+        (state.upvalues.len() - 1) as u8
+    }
+
+    /// consume a variable name and declare it
+    /// 
+    /// Returns the constant index of its name for globals, and `0` for locals.
+    fn parse_variable(&mut self, message: &str) -> u8 {
+        self.consume(TokenType::Identifier, message);
+        self.declare_variable();
+        if self.current_function().scope_depth > 0 {
+            return 0;
+        }
+        self.identifier_constant(self.previous.lexeme)
+    }
+
+    /// add the variable just named to the current scope, if it is local
+    fn declare_variable(&mut self) {
+        let name = self.previous.lexeme;
+        let function = self.current_function();
+        if function.scope_depth == 0 {
+            return;
+        }
+
+        let scope_depth = function.scope_depth;
+        let redeclared = function
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| !matches!(local.depth, Some(depth) if depth < scope_depth))
+            .any(|local| local.name == name);
+        if redeclared {
+            self.error("Already a variable with this name in this scope.");
+        }
+        self.add_local(name);
+    }
+
+    /// add an uninitialized local to the current scope
+    fn add_local(&mut self, name: &'src str) {
+        if self.current_function().locals.len() == MAX_LOCALS {
+            self.error("Too many local variables in function.");
+            return;
+        }
+        self.current_function().locals.push(Local {
+            name,
+            depth: None,
+            is_captured: false,
+        });
+    }
+
+    /// make the newest local usable now that its initializer is compiled
+    fn mark_initialized(&mut self) {
+        let function = self.current_function();
+        if function.scope_depth == 0 {
+            return;
+        }
+        let depth = function.scope_depth;
+        if let Some(local) = function.locals.last_mut() {
+            local.depth = Some(depth);
+        }
+    }
+
+    /// finish a variable declaration: a local is already in its slot, a
+    /// global is stored by name
+    fn define_variable(&mut self, global: u8) {
+        if self.current_function().scope_depth > 0 {
+            self.mark_initialized();
+            return;
+        }
+        self.emit_op_with(OpCode::DefineGlobal, global);
+    }
+
+    /// enter a block scope
+    fn begin_scope(&mut self) {
+        self.current_function().scope_depth += 1;
+    }
+
+    /// leave a block scope, popping its locals off the stack
+    /// 
+    /// Locals that closures captured are moved into their upvalues instead
+    /// of being discarded.
+    fn end_scope(&mut self) {
+        let function = self.current_function();
+        function.scope_depth -= 1;
+        let depth = function.scope_depth;
+
+        let mut popped = Vec::new();
+        while function.locals.last().is_some_and(|local| !matches!(local.depth, Some(d) if d <= depth)) {
+            let local = function.locals.pop().expect("the loop checked there is a local");
+            popped.push(local.is_captured);
+        }
+        for is_captured in popped {
+            self.emit_op(if is_captured { OpCode::CloseUpvalue } else { OpCode::Pop });
+        }
+    }
+
+    /// add a variable name to the constant pool
+    fn identifier_constant(&mut self, name: &str) -> u8 {
+        let name = self.intern(name);
+        self.make_constant(Value::Obj(name))
+    }
+
+    /// add a value to the constant pool, reporting an error if it is full
+    fn make_constant(&mut self, value: Value) -> u8 {
+        let constant = self.current_chunk().add_constant(value);
+        if constant >= MAX_CONSTANTS {
+            self.error("Too many constants in one chunk.");
+            return 0;
+        }
+        constant as u8
+    }
+
+    /// finish the innermost function, returning it and the variables it
+    /// captures
+    /// 
+    /// Garbage is collected first if due, while the function's constants
+    /// are still roots, so the caller can move it onto the heap right away.
+    fn end_function(&mut self) -> (ObjFunction, Vec<Upvalue>) {
+        self.emit_return();
+        self.collect_garbage_if_due();
+        let state = self.functions.pop().expect("a function is always being compiled");
+        (state.function, state.upvalues)
+    }
+
+    // ---- memory ----
+
+    /// intern a string, collecting garbage first if due
+    fn intern(&mut self, chars: &str) -> ObjRef {
+        self.collect_garbage_if_due();
+        self.heap.intern(chars)
+    }
+
+    /// collect garbage if enough has been allocated, keeping the caller's
+    /// roots and everything the functions being compiled refer to
+    fn collect_garbage_if_due(&mut self) {
+        if !self.heap.should_collect() {
+            return;
+        }
+        let compiler_roots = self.functions.iter().flat_map(|state| {
+            let function = &state.function;
+            function.name.map(Value::Obj).into_iter().chain(function.chunk.constants.iter().copied())
+        });
+        self.heap.collect(self.roots.iter().copied().chain(compiler_roots));
+    }
+
+    fn current_function(&mut self) -> &mut FunctionState<'src> {
+        self.functions.last_mut().expect("a function is always being compiled")
+    }
+
+    fn current_class(&mut self) -> &mut ClassState {
+        self.classes.last_mut().expect("a class is being compiled")
+    }
+
+    fn current_chunk(&mut self) -> &mut Chunk {
+        &mut self.current_function().function.chunk
+    }
+
+    // ---- emitting bytecode ----
+
+    /// append a byte, on the line of the previous token
+    fn emit_byte(&mut self, byte: u8) {
+        let line = self.previous.line;
+        self.current_chunk().write(byte, line);
+    }
+
+    fn emit_op(&mut self, op: OpCode) {
+        self.emit_byte(op as u8);
+    }
+
+    /// append an instruction on a given line
+    fn emit_op_at(&mut self, op: OpCode, line: usize) {
+        self.current_chunk().write(op as u8, line);
+    }
+
+    /// append an instruction with a one-byte operand
+    fn emit_op_with(&mut self, op: OpCode, operand: u8) {
+        self.emit_op(op);
+        self.emit_byte(operand);
+    }
+
+    /// append the implicit return at the end of a function
+    fn emit_return(&mut self) {
+        if self.current_function().kind == FunctionKind::Initializer {
+            self.emit_op_with(OpCode::GetLocal, 0);
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+        self.emit_op(OpCode::Return);
+    }
+
+    /// append a forward jump with a placeholder offset
+    /// 
+    /// Returns the position of the offset, for `patch_jump`.
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_op(op);
+        self.emit_byte(0xff);
+        self.emit_byte(0xff);
+        self.current_chunk().code.len() - 2
+    }
+
+    /// point a forward jump at the next instruction to be emitted
+    fn patch_jump(&mut self, offset: usize) {
+        let jump = self.current_chunk().code.len() - offset - 2;
+        let Ok(jump) = u16::try_from(jump) else {
+            self.error("Too much code to jump over.");
+            return;
+        };
+        let [high, low] = jump.to_be_bytes();
+        let code = &mut self.current_chunk().code;
+        code[offset] = high;
+        code[offset + 1] = low;
+    }
+
+    /// append a backward jump to `loop_start`
+    fn emit_loop(&mut self, loop_start: usize) {
+        self.emit_op(OpCode::Loop);
+        let offset = self.current_chunk().code.len() - loop_start + 2;
+        let offset = u16::try_from(offset).unwrap_or_else(|_| {
+            self.error("Loop body too large.");
+            0
+        });
+        let [high, low] = offset.to_be_bytes();
+        self.emit_byte(high);
+        self.emit_byte(low);
+    }
+
+    // ---- tokens ----
+
+    /// move to the next token, reporting any lexical errors on the way
+    fn advance(&mut self) {
+        let line = self.current.line;
+        self.previous = std::mem::replace(&mut self.current, Token::new(TokenType::Eof, "", line));
+        loop {
+            match self.scanner.next() {
+                Some(Ok(token)) if token.token_type.is_trivia() => continue,
+                Some(Ok(token)) => {
+                    self.current = token;
+                    return;
+                }
+                Some(Err(error)) => self.scan_error(&error),
+                // no more tokens: `current` stays at end of file, on the last line
+                None => return,
+            }
+        }
+    }
+
+    fn check(&self, token_type: TokenType) -> bool {
+        self.current.token_type == token_type
+    }
+
+    fn match_(&mut self, token_type: TokenType) -> bool {
+        if !self.check(token_type) {
+            return false;
+        }
+        self.advance();
+        true
+    }
+
+    /// consume a token of the given type, or report an error at the current one
+    fn consume(&mut self, token_type: TokenType, message: &str) {
+        if self.check(token_type) {
+            self.advance();
+        } else {
+            self.error_at_current(message);
+        }
+    }
+
+    // ---- errors ----
+
+    /// report an error at the token just consumed
+    fn error(&mut self, message: &str) {
+        let location = location(&self.previous);
+        self.report(self.previous.line, location, message);
+    }
+
+    /// report an error at the next token
+    fn error_at_current(&mut self, message: &str) {
+        let location = location(&self.current);
+        self.report(self.current.line, location, message);
+    }
+
+    /// report a lexical error, at the unscannable text or at the end
+    fn scan_error(&mut self, error: &ScanError) {
+        self.report(error.span.line, error.location(), &error.message);
+    }
+
+    /// record an error unless one was already reported for this statement
+    fn report(&mut self, line: usize, location: String, message: &str) {
+        if self.panic_mode {
+            return;
+        }
+        self.panic_mode = true;
+        self.errors.push(CompileError {
+            line,
+            location,
+            message: message.to_string(),
+        });
+    }
+}
+
+/// where on its line an error at a token is
+fn location(token: &Token) -> String {
+    match token.token_type {
+        TokenType::Eof => " at end".to_string(),
+        _ => format!(" at '{}'", token.lexeme),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::disassemble_instruction;
+
+    // 辅助函数：编译源代码，返回错误信息
+    fn errors(source: &str) -> Vec<String> {
+        let mut heap = Heap::new();
+        match compile(source, &mut heap) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.iter().map(|error| error.to_string()).collect(),
+        }
+    }
+
+    // 辅助函数：编译源代码，返回顶层代码的指令
+    fn ops(source: &str) -> Vec<OpCode> {
+        let mut heap = Heap::new();
+        let script = compile(source, &mut heap).expect("测试源代码应该能编译");
+        let chunk = &heap.function(script).chunk;
+        let mut ops = Vec::new();
+        let mut offset = 0;
+        while offset < chunk.code.len() {
+            ops.push(OpCode::from_byte(chunk.code[offset]).unwrap());
+            (_, offset) = disassemble_instruction(chunk, &heap, offset);
+        }
+        ops
+    }
+
+    #[test]
+    fn test_compiles_expressions() {
+        use OpCode::*;
+        assert_eq!(ops("print 1 + 2 * 3;"), [Constant, Constant, Constant, Multiply, Add, Print, Nil, Return]);
+        assert_eq!(ops("print !(1 <= 2);"), [Constant, Constant, Greater, Not, Not, Print, Nil, Return]);
+        assert_eq!(ops("-nil;"), [Nil, Negate, Pop, Nil, Return]);
+    }
+
+    #[test]
+    fn test_compiles_variables() {
+        use OpCode::*;
+        assert_eq!(ops("var a = 1; a = a;"), [Constant, DefineGlobal, GetGlobal, SetGlobal, Pop, Nil, Return]);
+        assert_eq!(ops("{ var a; a = a; }"), [Nil, GetLocal, SetLocal, Pop, Pop, Nil, Return]);
+    }
+
+    #[test]
+    fn test_compiles_control_flow() {
+        use OpCode::*;
+        assert_eq!(ops("while (true) nil;"), [True, JumpIfFalse, Pop, Nil, Pop, Loop, Pop, Nil, Return]);
+        assert_eq!(ops("true and false;"), [True, JumpIfFalse, Pop, False, Pop, Nil, Return]);
+    }
+
+    #[test]
+    fn test_jump_offsets() {
+        let mut heap = Heap::new();
+        let script = compile("if (true) nil;", &mut heap).unwrap();
+        let code = &heap.function(script).chunk.code;
+        // True, JumpIfFalse +6, Pop, Nil, Pop, Jump +1, Pop, Nil, Return
+        assert_eq!(&code[1..4], [OpCode::JumpIfFalse as u8, 0, 6]);
+        assert_eq!(&code[7..10], [OpCode::Jump as u8, 0, 1]);
+    }
+
+    #[test]
+    fn test_functions_are_constants() {
+        let mut heap = Heap::new();
+        let script = compile("fun add(a, b) { return a + b; }", &mut heap).unwrap();
+        let constants = &heap.function(script).chunk.constants;
+        let function = constants
+            .iter()
+            .find_map(|constant| match constant {
+                Value::Obj(object) if matches!(heap.get(*object), Obj::Function(_)) => Some(*object),
+                _ => None,
+            })
+            .expect("函数应该是顶层代码的常量");
+        assert_eq!(heap.function(function).arity, 2);
+        assert_eq!(heap.display(Value::Obj(function)).to_string(), "<fn add>");
+    }
+
+    #[test]
+    fn test_captured_locals_become_upvalues() {
+        let mut heap = Heap::new();
+        let source = "fun outer() { var a; var b; fun middle() { fun inner() { b; a; b = a; } } }";
+        let script = compile(source, &mut heap).unwrap();
+        let functions: Vec<ObjRef> = (0..heap.len())
+            .map(ObjRef)
+            .filter(|&object| matches!(heap.get(object), Obj::Function(_)))
+            .collect();
+        let upvalue_count = |name: &str| {
+            let function = functions
+                .iter()
+                .map(|&function| heap.function(function))
+                .find(|function| function.name.is_some_and(|n| heap.string(n) == name))
+                .unwrap();
+            function.upvalue_count
+        };
+        // 同一个变量只捕获一次，中间的函数也要捕获才能传递给内层函数
+        assert_eq!(upvalue_count("inner"), 2);
+        assert_eq!(upvalue_count("middle"), 2);
+        assert_eq!(upvalue_count("outer"), 0);
+        assert_eq!(heap.function(script).upvalue_count, 0);
+    }
+
+    #[test]
+    fn test_captured_locals_are_closed() {
+        use OpCode::*;
+        assert_eq!(
+            ops("{ var a; var b; fun f() { a; } }"),
+            [Nil, Nil, Closure, Pop, Pop, CloseUpvalue, Nil, Return]
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        assert_eq!(errors("print 1"), ["[line 1] Error at end: Expect ';' after value."]);
+        assert_eq!(errors("var = 1;"), ["[line 1] Error at '=': Expect variable name."]);
+        assert_eq!(errors("1 + ;"), ["[line 1] Error at ';': Expect expression."]);
+        assert_eq!(errors("a + b = c;"), ["[line 1] Error at '=': Invalid assignment target."]);
+        assert_eq!(errors("print @;"), ["[line 1] Error at '@': Unexpected character."]);
+        // 输入的最后一个字节是非法字符时，错误仍然报告在该字符处
+        assert_eq!(errors("print 1 + ~"), ["[line 1] Error at '~': Unexpected character."]);
+        assert_eq!(errors("print \"abc"), ["[line 1] Error at end: Unterminated string."]);
+    }
+
+    #[test]
+    fn test_recovers_at_statement_boundaries() {
+        assert_eq!(
+            errors("print;\nvar x = 1;\nprint (1;\nprint 2;"),
+            [
+                "[line 1] Error at ';': Expect expression.",
+                "[line 3] Error at ';': Expect ')' after expression.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_static_errors() {
+        assert_eq!(errors("return 1;"), ["[line 1] Error at 'return': Can't return from top-level code."]);
+        assert_eq!(
+            errors("{ var a = 1; { var a = a; } }"),
+            ["[line 1] Error at 'a': Can't read local variable in its own initializer."]
+        );
+        assert_eq!(
+            errors("{ var a; var a; }"),
+            ["[line 1] Error at 'a': Already a variable with this name in this scope."]
+        );
+        assert_eq!(
+            errors("class A { init() { return 1; } }"),
+            ["[line 1] Error at 'return': Can't return a value from an initializer."]
+        );
+        assert_eq!(errors("this;"), ["[line 1] Error at 'this': Can't use 'this' outside of a class."]);
+        assert_eq!(
+            errors("class A { m() { super.m(); } }"),
+            ["[line 1] Error at 'super': Can't use 'super' in a class with no superclass."]
+        );
+        assert_eq!(errors("class A < A {}"), ["[line 1] Error at 'A': A class can't inherit from itself."]);
+        assert!(errors("var a; var a; { var b; } { var b; }").is_empty());
+    }
+
+    #[test]
+    fn test_too_many_constants() {
+        let numbers: Vec<String> = (0..300).map(|n| n.to_string()).collect();
+        let source = format!("print {};", numbers.join(" + "));
+        assert_eq!(errors(&source), ["[line 1] Error at '256': Too many constants in one chunk."]);
+    }
+}
@@ -0,0 +1,260 @@
+use std::fmt::Write;
+
+use super::{Chunk, Heap, Obj, ObjRef, OpCode, Value};
+
+/// Lists every instruction in a chunk
+/// 
+/// Each line shows the instruction's offset, its source line (`|` when it
+/// is the same as the previous instruction's), the opcode and its
+/// operands. Constant operands are followed by the constant's value.
+/// 
+/// # Arguments
+/// 
+/// * `chunk` - The bytecode to list
+/// * `heap` - The heap the chunk's constants live on
+/// * `name` - A heading for the listing, such as the function's name
+/// 
+/// # Returns
+/// 
+/// The listing, one line per instruction after a `== name ==` heading
+/// 
+/// # Examples
+/// 
+/// ```
+/// use rlox::vm::{compile, disassemble_chunk, Heap};
+/// 
+/// let mut heap = Heap::new();
+/// let script = compile("print 1;", &mut heap).unwrap();
+/// let listing = disassemble_chunk(&heap.function(script).chunk, &heap, "script");
+/// 
+/// assert_eq!(
+///     listing,
+///     "== script ==\n\
+///      0000    1 OP_CONSTANT         0 '1'\n\
+///      0002    | OP_PRINT\n\
+///      0003    | OP_NIL\n\
+///      0004    | OP_RETURN\n"
+/// );
+/// ```
+pub fn disassemble_chunk(chunk: &Chunk, heap: &Heap, name: &str) -> String {
+    let mut listing = format!("== {} ==\n", name);
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let (line, next) = disassemble_instruction(chunk, heap, offset);
+        listing.push_str(&line);
+        listing.push('\n');
+        offset = next;
+    }
+    listing
+}
+
+/// Lists a compiled function and, after it, every function it contains
+/// 
+/// # Arguments
+/// 
+/// * `function` - The function to list, such as the result of
+///   [`compile`](super::compile)
+/// * `heap` - The heap the function lives on
+pub fn disassemble_function(function: ObjRef, heap: &Heap) -> String {
+    let object = heap.function(function);
+    let name = match object.name {
+        Some(name) => heap.string(name),
+        None => "<script>",
+    };
+
+    let mut listing = disassemble_chunk(&object.chunk, heap, name);
+    for constant in &object.chunk.constants {
+        if let Value::Obj(nested) = *constant {
+            if let Obj::Function(_) = heap.get(nested) {
+                listing.push_str(&disassemble_function(nested, heap));
+            }
+        }
+    }
+    listing
+}
+
+/// Describes the instruction at `offset`
+/// 
+/// # Arguments
+/// 
+/// * `chunk` - The bytecode the instruction is in
+/// * `heap` - The heap the chunk's constants live on
+/// * `offset` - The offset of the instruction's opcode
+/// 
+/// # Returns
+/// 
+/// The description, without a trailing newline, and the offset of the
+/// next instruction
+pub fn disassemble_instruction(chunk: &Chunk, heap: &Heap, offset: usize) -> (String, usize) {
+    let mut text = format!("{:04} ", offset);
+    let line = chunk.line(offset);
+    if offset > 0 && line == chunk.line(offset - 1) {
+        text.push_str("   | ");
+    } else {
+        let _ = write!(text, "{:4} ", line);
+    }
+
+    let byte = chunk.code[offset];
+    let Some(op) = OpCode::from_byte(byte) else {
+        let _ = write!(text, "Unknown opcode {}", byte);
+        return (text, offset + 1);
+    };
+
+    let next = match op {
+        OpCode::Constant
+        | OpCode::GetGlobal
+        | OpCode::DefineGlobal
+        | OpCode::SetGlobal
+        | OpCode::GetProperty
+        | OpCode::SetProperty
+        | OpCode::GetSuper
+        | OpCode::Class
+        | OpCode::Method => constant_instruction(&mut text, op, chunk, heap, offset),
+        OpCode::GetLocal | OpCode::SetLocal | OpCode::GetUpvalue | OpCode::SetUpvalue | OpCode::Call => {
+            byte_instruction(&mut text, op, chunk, offset)
+        }
+        OpCode::Jump | OpCode::JumpIfFalse => jump_instruction(&mut text, op, true, chunk, offset),
+        OpCode::Loop => jump_instruction(&mut text, op, false, chunk, offset),
+        OpCode::Invoke | OpCode::SuperInvoke => invoke_instruction(&mut text, op, chunk, heap, offset),
+        OpCode::Closure => closure_instruction(&mut text, op, chunk, heap, offset),
+        _ => {
+            let _ = write!(text, "{}", op);
+            offset + 1
+        }
+    };
+    (text, next)
+}
+
+/// an instruction whose operand indexes the constant pool
+fn constant_instruction(text: &mut String, op: OpCode, chunk: &Chunk, heap: &Heap, offset: usize) -> usize {
+    let constant = chunk.code[offset + 1];
+    let value = chunk.constants[constant as usize];
+    let _ = write!(text, "{:<16} {:4} '{}'", op, constant, heap.display(value));
+    offset + 2
+}
+
+/// an instruction with a one-byte operand, such as a stack slot
+fn byte_instruction(text: &mut String, op: OpCode, chunk: &Chunk, offset: usize) -> usize {
+    let _ = write!(text, "{:<16} {:4}", op, chunk.code[offset + 1]);
+    offset + 2
+}
+
+/// a jump, shown with the offset it lands on
+fn jump_instruction(text: &mut String, op: OpCode, forward: bool, chunk: &Chunk, offset: usize) -> usize {
+    let jump = u16::from_be_bytes([chunk.code[offset + 1], chunk.code[offset + 2]]) as usize;
+    let target = if forward { offset + 3 + jump } else { offset + 3 - jump };
+    let _ = write!(text, "{:<16} {:4} -> {}", op, offset, target);
+    offset + 3
+}
+
+/// a method call, with the method name and the argument count
+fn invoke_instruction(text: &mut String, op: OpCode, chunk: &Chunk, heap: &Heap, offset: usize) -> usize {
+    let constant = chunk.code[offset + 1];
+    let arg_count = chunk.code[offset + 2];
+    let value = chunk.constants[constant as usize];
+    let _ = write!(text, "{:<16} ({} args) {:4} '{}'", op, arg_count, constant, heap.display(value));
+    offset + 3
+}
+
+/// a closure, with one extra line per captured variable
+fn closure_instruction(text: &mut String, op: OpCode, chunk: &Chunk, heap: &Heap, offset: usize) -> usize {
+    let mut next = constant_instruction(text, op, chunk, heap, offset);
+    let Value::Obj(function) = chunk.constants[chunk.code[offset + 1] as usize] else {
+        return next;
+    };
+
+    for _ in 0..heap.function(function).upvalue_count {
+        let kind = if chunk.code[next] == 1 { "local" } else { "upvalue" };
+        let index = chunk.code[next + 1];
+        let _ = write!(text, "\n{:04}      |                     {} {}", next, kind, index);
+        next += 2;
+    }
+    next
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::compile;
+
+    // 辅助函数：编译源代码，返回顶层代码的反汇编结果
+    fn listing(source: &str) -> String {
+        let mut heap = Heap::new();
+        let script = compile(source, &mut heap).expect("测试源代码应该能编译");
+        disassemble_chunk(&heap.function(script).chunk, &heap, "test")
+    }
+
+    #[test]
+    fn test_operands_and_lines() {
+        let expected = "\
+== test ==
+0000    1 OP_CONSTANT         1 'a'
+0002    | OP_DEFINE_GLOBAL    0 'x'
+0004    2 OP_GET_GLOBAL       2 'x'
+0006    | OP_PRINT
+0007    | OP_NIL
+0008    | OP_RETURN
+";
+        assert_eq!(listing("var x = \"a\";\nprint x;"), expected);
+    }
+
+    #[test]
+    fn test_jumps_show_their_targets() {
+        // 条件为假时跳出循环，循环体结束后跳回条件
+        let expected = "\
+== test ==
+0000    1 OP_TRUE
+0001    | OP_JUMP_IF_FALSE    1 -> 8
+0004    | OP_POP
+0005    | OP_LOOP             5 -> 0
+0008    | OP_POP
+0009    | OP_NIL
+0010    | OP_RETURN
+";
+        assert_eq!(listing("while (true) {}"), expected);
+    }
+
+    #[test]
+    fn test_locals_calls_and_invokes() {
+        let listing = listing("{ var a; a(1); a.b(2, 3); }");
+        assert!(listing.contains("OP_GET_LOCAL        1\n"));
+        assert!(listing.contains("OP_CALL             1\n"));
+        assert!(listing.contains("OP_INVOKE        (2 args)    1 'b'\n"));
+    }
+
+    #[test]
+    fn test_nested_functions_are_listed() {
+        let mut heap = Heap::new();
+        let script = compile("fun f() { return 1; }", &mut heap).unwrap();
+        let listing = disassemble_function(script, &heap);
+        let headings: Vec<&str> = listing.lines().filter(|line| line.starts_with("==")).collect();
+        assert_eq!(headings, ["== <script> ==", "== f =="]);
+        assert!(listing.contains("OP_CLOSURE          1 '<fn f>'"));
+    }
+
+    #[test]
+    fn test_closures_list_their_upvalues() {
+        let mut heap = Heap::new();
+        let source = "fun outer() {\n  var x;\n  fun inner() { x; }\n}";
+        let script = compile(source, &mut heap).unwrap();
+        let listing = disassemble_function(script, &heap);
+        let outer: Vec<&str> = listing.lines().skip_while(|line| *line != "== outer ==").collect();
+        assert_eq!(
+            &outer[1..5],
+            [
+                "0000    2 OP_NIL",
+                "0001    3 OP_CLOSURE          0 '<fn inner>'",
+                "0003      |                     local 1",
+                "0005    4 OP_NIL",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_opcode() {
+        let mut chunk = Chunk::new();
+        chunk.write(255, 1);
+        let (text, next) = disassemble_instruction(&chunk, &Heap::new(), 0);
+        assert_eq!((text.as_str(), next), ("0000    1 Unknown opcode 255", 1));
+    }
+}
@@ -0,0 +1,584 @@
+use std::{fmt, mem};
+
+use super::{
+    table::{hash_string, Table},
+    Obj, ObjClosure, ObjFunction, ObjRef, ObjString, ObjUpvalue, Value,
+};
+
+/// How many bytes may be allocated before the first collection
+const FIRST_GC: usize = 1024 * 1024;
+/// How much the heap may grow after a collection before the next one,
+/// relative to what survived it
+const GC_HEAP_GROW_FACTOR: usize = 2;
+/// The bytes a class uses per method, or an instance per field
+pub(super) const PROPERTY_SIZE: usize = mem::size_of::<(ObjRef, Value)>();
+/// The bytes a closure uses per captured variable
+pub(super) const CAPTURE_SIZE: usize = mem::size_of::<ObjRef>();
+
+/// Owner of every object the virtual machine allocates
+/// 
+/// Objects are stored in a vector and referred to by index through
+/// [`ObjRef`] handles, so values can be copied without reference counting.
+/// 
+/// Memory is reclaimed by a tracing mark-and-sweep collector. The heap
+/// does not know which objects are in use, so it never collects on its
+/// own: the virtual machine and the compiler check [`Heap::should_collect`]
+/// before allocating and call [`Heap::collect`] with their roots.
+/// 
+/// Strings are interned: [`Heap::intern`] returns the existing string
+/// when there is one with the same contents. The intern table holds its
+/// strings weakly, so strings that are otherwise unreachable are still
+/// collected.
+/// 
+/// # Fields
+/// 
+/// * `objects` - The allocated objects, indexed by handle; `None` for
+///   slots freed by the collector
+/// * `free` - Freed slots that new objects can reuse
+/// * `strings` - The interned strings
+/// * `marks` - Which objects the current collection has reached
+/// * `gray` - Objects reached but not yet traced
+/// * `bytes_allocated` - The approximate size of the live objects
+/// * `next_gc` - The size at which the next collection is due
+/// * `stress` - Whether to collect before every allocation
+/// * `log` - Whether to log allocations and collections to stderr
+#[derive(Debug)]
+pub struct Heap {
+    objects: Vec<Option<Obj>>,
+    free: Vec<usize>,
+    strings: Table,
+    marks: Vec<bool>,
+    gray: Vec<ObjRef>,
+    bytes_allocated: usize,
+    next_gc: usize,
+    stress: bool,
+    log: bool,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Heap::new()
+    }
+}
+
+impl Heap {
+    /// Creates an empty heap
+    pub fn new() -> Heap {
+        Heap {
+            objects: Vec::new(),
+            free: Vec::new(),
+            strings: Table::new(),
+            marks: Vec::new(),
+            gray: Vec::new(),
+            bytes_allocated: 0,
+            next_gc: FIRST_GC,
+            stress: false,
+            log: false,
+        }
+    }
+
+    /// Makes [`Heap::should_collect`] true before every allocation, so
+    /// objects that are missing from the roots are freed right away
+    pub fn set_stress(&mut self, enabled: bool) {
+        self.stress = enabled;
+    }
+
+    /// Logs every allocation, freed object and collection to stderr
+    pub fn set_log(&mut self, enabled: bool) {
+        self.log = enabled;
+    }
+
+    /// Moves an object onto the heap
+    /// 
+    /// Never collects garbage, so handles held by the caller stay valid.
+    /// Strings must be created with [`Heap::intern`] instead.
+    /// 
+    /// # Returns
+    /// 
+    /// A handle to the object
+    pub fn alloc(&mut self, object: Obj) -> ObjRef {
+        debug_assert!(!matches!(object, Obj::String(_)), "strings must be interned");
+        self.alloc_object(object)
+    }
+
+    /// move any object, including a string, onto the heap
+    fn alloc_object(&mut self, object: Obj) -> ObjRef {
+        let size = size_of_object(&object);
+        self.bytes_allocated += size;
+        if self.log {
+            let index = self.free.last().copied().unwrap_or(self.objects.len());
+            eprintln!("{} allocate {} for {}", index, size, kind(&object));
+        }
+
+        match self.free.pop() {
+            Some(index) => {
+                self.objects[index] = Some(object);
+                ObjRef(index)
+            }
+            None => {
+                self.objects.push(Some(object));
+                self.marks.push(false);
+                ObjRef(self.objects.len() - 1)
+            }
+        }
+    }
+
+    /// Returns the string with the given contents, allocating it if there
+    /// is none yet
+    /// 
+    /// Like [`Heap::alloc`], never collects garbage.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `chars` - The string's contents
+    /// 
+    /// # Returns
+    /// 
+    /// A handle that is equal to every other handle to a string with the
+    /// same contents
+    pub fn intern(&mut self, chars: &str) -> ObjRef {
+        let hash = hash_string(chars);
+        let objects = &self.objects;
+        let existing = self.strings.find(hash, |key| {
+            matches!(&objects[key.0], Some(Obj::String(string)) if *string.chars == *chars)
+        });
+        if let Some(string) = existing {
+            return string;
+        }
+
+        let string = self.alloc_object(Obj::String(ObjString {
+            chars: chars.into(),
+            hash,
+        }));
+        self.strings.insert(string, hash);
+        string
+    }
+
+    /// Records that an object grew after it was allocated
+    /// 
+    /// Objects such as instances gain fields over time; the growth counts
+    /// towards the next collection just as new objects do. Like
+    /// [`Heap::alloc`], never collects garbage, so the caller should check
+    /// [`Heap::should_collect`] afterwards.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `object` - The object that grew
+    /// * `bytes` - How many bytes it grew by
+    pub fn grew(&mut self, object: ObjRef, bytes: usize) {
+        self.bytes_allocated += bytes;
+        if self.log {
+            eprintln!("{} grow {} for {}", object.0, bytes, kind(self.get(object)));
+        }
+    }
+
+    /// Returns the number of live objects on the heap
+    pub fn len(&self) -> usize {
+        self.objects.len() - self.free.len()
+    }
+
+    /// Checks whether there are no live objects
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the approximate number of bytes the live objects use
+    pub fn bytes_allocated(&self) -> usize {
+        self.bytes_allocated
+    }
+
+    /// Checks whether enough has been allocated that a collection is due
+    pub fn should_collect(&self) -> bool {
+        self.stress || self.bytes_allocated > self.next_gc
+    }
+
+    /// Frees every object that cannot be reached from the roots
+    /// 
+    /// Tri-color marking: the roots are marked gray, then gray objects are
+    /// taken off a worklist one at a time and blackened by marking what
+    /// they refer to. When the worklist is empty, unmarked objects are
+    /// unreachable: they are removed from the intern table and swept. The
+    /// next collection is due once the heap has grown by
+    /// [`GC_HEAP_GROW_FACTOR`] over what survived.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `roots` - Every value the caller may still use; handles to other
+    ///   objects are invalid afterwards
+    pub fn collect(&mut self, roots: impl IntoIterator<Item = Value>) {
+        let before = self.bytes_allocated;
+        if self.log {
+            eprintln!("-- gc begin");
+        }
+
+        for root in roots {
+            self.mark_value(root);
+        }
+        self.trace_references();
+        let marks = &self.marks;
+        self.strings.retain(|string| marks[string.0]);
+        self.sweep();
+
+        self.bytes_allocated = self.objects.iter().flatten().map(size_of_object).sum();
+        self.next_gc = (self.bytes_allocated * GC_HEAP_GROW_FACTOR).max(FIRST_GC);
+        if self.log {
+            eprintln!("-- gc end");
+            eprintln!(
+                "   collected {} bytes (from {} to {}) next at {}",
+                before.saturating_sub(self.bytes_allocated),
+                before,
+                self.bytes_allocated,
+                self.next_gc
+            );
+        }
+    }
+
+    /// mark a value's object gray, if it has one
+    fn mark_value(&mut self, value: Value) {
+        if let Value::Obj(object) = value {
+            self.mark_object(object);
+        }
+    }
+
+    /// mark an object gray, unless it was already reached
+    fn mark_object(&mut self, object: ObjRef) {
+        if self.marks[object.0] {
+            return;
+        }
+        self.marks[object.0] = true;
+        self.gray.push(object);
+    }
+
+    /// blacken gray objects until none are left
+    fn trace_references(&mut self) {
+        while let Some(object) = self.gray.pop() {
+            self.blacken(object);
+        }
+    }
+
+    /// mark everything an object refers to
+    fn blacken(&mut self, object: ObjRef) {
+        let mut children = Vec::new();
+        match self.get(object) {
+            Obj::String(_) | Obj::Native(_) => (),
+            Obj::Function(function) => {
+                children.extend(function.name.map(Value::Obj));
+                children.extend(function.chunk.constants.iter().copied());
+            }
+            Obj::Closure(closure) => {
+                children.push(Value::Obj(closure.function));
+                children.extend(closure.upvalues.iter().copied().map(Value::Obj));
+            }
+            // an open upvalue's variable is on the stack, which is a root
+            Obj::Upvalue(ObjUpvalue::Open(_)) => (),
+            Obj::Upvalue(ObjUpvalue::Closed(value)) => children.push(*value),
+            Obj::Class(class) => {
+                children.push(Value::Obj(class.name));
+                children.extend(class.methods.keys().copied().map(Value::Obj));
+                children.extend(class.methods.values().copied());
+            }
+            Obj::Instance(instance) => {
+                children.push(Value::Obj(instance.class));
+                children.extend(instance.fields.keys().copied().map(Value::Obj));
+                children.extend(instance.fields.values().copied());
+            }
+            Obj::BoundMethod(bound) => {
+                children.push(bound.receiver);
+                children.push(Value::Obj(bound.method));
+            }
+        }
+        for child in children {
+            self.mark_value(child);
+        }
+    }
+
+    /// free every unmarked object and clear the marks for next time
+    fn sweep(&mut self) {
+        for (index, slot) in self.objects.iter_mut().enumerate() {
+            if mem::take(&mut self.marks[index]) {
+                continue;
+            }
+            if let Some(object) = slot.take() {
+                if self.log {
+                    eprintln!("{} free {}", index, kind(&object));
+                }
+                self.free.push(index);
+            }
+        }
+    }
+
+    /// Returns the object a handle refers to
+    /// 
+    /// # Panics
+    /// 
+    /// If the object has been freed
+    pub fn get(&self, object: ObjRef) -> &Obj {
+        self.objects[object.0].as_ref().expect("use of a freed object")
+    }
+
+    /// Returns the object a handle refers to, for modification
+    /// 
+    /// # Panics
+    /// 
+    /// If the object has been freed
+    pub fn get_mut(&mut self, object: ObjRef) -> &mut Obj {
+        self.objects[object.0].as_mut().expect("use of a freed object")
+    }
+
+    /// Returns the contents of a string object
+    /// 
+    /// # Panics
+    /// 
+    /// If the handle does not refer to a string
+    pub fn string(&self, object: ObjRef) -> &str {
+        match self.get(object) {
+            Obj::String(string) => &string.chars,
+            other => panic!("expected a string, found {:?}", other),
+        }
+    }
+
+    /// Returns a function object
+    /// 
+    /// # Panics
+    /// 
+    /// If the handle does not refer to a function
+    pub fn function(&self, object: ObjRef) -> &ObjFunction {
+        match self.get(object) {
+            Obj::Function(function) => function,
+            other => panic!("expected a function, found {:?}", other),
+        }
+    }
+
+    /// Returns a closure object
+    /// 
+    /// # Panics
+    /// 
+    /// If the handle does not refer to a closure
+    pub fn closure(&self, object: ObjRef) -> &ObjClosure {
+        match self.get(object) {
+            Obj::Closure(closure) => closure,
+            other => panic!("expected a closure, found {:?}", other),
+        }
+    }
+
+    /// Returns an upvalue object
+    /// 
+    /// # Panics
+    /// 
+    /// If the handle does not refer to an upvalue
+    pub fn upvalue(&self, object: ObjRef) -> ObjUpvalue {
+        match self.get(object) {
+            Obj::Upvalue(upvalue) => *upvalue,
+            other => panic!("expected an upvalue, found {:?}", other),
+        }
+    }
+
+    /// Checks whether a value is a string object
+    pub fn is_string(&self, value: Value) -> bool {
+        matches!(value, Value::Obj(object) if matches!(self.get(object), Obj::String(_)))
+    }
+
+    /// Formats a value the way `print` shows it
+    pub fn display(&self, value: Value) -> ValueDisplay<'_> {
+        ValueDisplay { heap: self, value }
+    }
+
+    /// the text `print` shows for a function
+    fn function_name(&self, function: ObjRef) -> String {
+        match self.function(function).name {
+            Some(name) => format!("<fn {}>", self.string(name)),
+            None => "<script>".to_string(),
+        }
+    }
+}
+
+/// the approximate number of bytes an object uses, including what it owns
+fn size_of_object(object: &Obj) -> usize {
+    let owned = match object {
+        Obj::String(string) => string.chars.len(),
+        Obj::Function(function) => {
+            function.chunk.code.len() * 2 + function.chunk.constants.len() * mem::size_of::<Value>()
+        }
+        Obj::Closure(closure) => closure.upvalues.len() * CAPTURE_SIZE,
+        Obj::Class(class) => class.methods.len() * PROPERTY_SIZE,
+        Obj::Instance(instance) => instance.fields.len() * PROPERTY_SIZE,
+        Obj::Upvalue(_) | Obj::Native(_) | Obj::BoundMethod(_) => 0,
+    };
+    mem::size_of::<Obj>() + owned
+}
+
+/// the kind of an object, for the GC log
+fn kind(object: &Obj) -> &'static str {
+    match object {
+        Obj::String(_) => "string",
+        Obj::Function(_) => "function",
+        Obj::Closure(_) => "closure",
+        Obj::Upvalue(_) => "upvalue",
+        Obj::Native(_) => "native",
+        Obj::Class(_) => "class",
+        Obj::Instance(_) => "instance",
+        Obj::BoundMethod(_) => "bound method",
+    }
+}
+
+/// A value paired with the heap it lives on, for printing
+pub struct ValueDisplay<'heap> {
+    heap: &'heap Heap,
+    value: Value,
+}
+
+impl fmt::Display for ValueDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let heap = self.heap;
+        let object = match self.value {
+            Value::Nil => return write!(f, "nil"),
+            Value::Bool(b) => return write!(f, "{}", b),
+            Value::Number(n) => return write!(f, "{}", n),
+            Value::Obj(object) => object,
+        };
+        match heap.get(object) {
+            Obj::String(string) => write!(f, "{}", string.chars),
+            Obj::Function(_) => write!(f, "{}", heap.function_name(object)),
+            Obj::Closure(closure) => write!(f, "{}", heap.function_name(closure.function)),
+            Obj::Upvalue(_) => write!(f, "upvalue"),
+            Obj::Native(_) => write!(f, "<native fn>"),
+            Obj::Class(class) => write!(f, "{}", heap.string(class.name)),
+            Obj::Instance(instance) => match heap.get(instance.class) {
+                Obj::Class(class) => write!(f, "{} instance", heap.string(class.name)),
+                _ => unreachable!("an instance's class is always a class"),
+            },
+            Obj::BoundMethod(bound) => write!(f, "{}", heap.function_name(heap.closure(bound.method).function)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{ObjClass, ObjInstance};
+    use std::collections::HashMap;
+
+    // 辅助函数：检查对象是否仍然存活
+    fn is_live(heap: &Heap, object: ObjRef) -> bool {
+        heap.objects[object.0].is_some()
+    }
+
+    #[test]
+    fn test_collect_frees_unreachable_objects() {
+        let mut heap = Heap::new();
+        let kept = heap.intern("kept");
+        let garbage = heap.intern("garbage");
+
+        heap.collect([Value::Obj(kept), Value::Number(1.0)]);
+        assert!(is_live(&heap, kept));
+        assert!(!is_live(&heap, garbage));
+        assert_eq!(heap.len(), 1);
+        assert_eq!(heap.string(kept), "kept");
+    }
+
+    #[test]
+    fn test_collect_traces_references() {
+        let mut heap = Heap::new();
+        let name = heap.intern("Point");
+        let class = heap.alloc(Obj::Class(ObjClass {
+            name,
+            methods: HashMap::new(),
+        }));
+        let field_name = heap.intern("x");
+        let field = heap.intern("field value");
+        let instance = heap.alloc(Obj::Instance(ObjInstance {
+            class,
+            fields: HashMap::from([(field_name, Value::Obj(field))]),
+        }));
+        let closed = heap.alloc(Obj::Upvalue(ObjUpvalue::Closed(Value::Obj(instance))));
+        let function = heap.alloc(Obj::Function(ObjFunction::default()));
+        let closure = heap.alloc(Obj::Closure(ObjClosure {
+            function,
+            upvalues: vec![closed],
+        }));
+
+        // 闭包 → upvalue → 实例 → 类与字段（包括字段名），整条引用链都应该保留
+        heap.collect([Value::Obj(closure)]);
+        assert_eq!(heap.len(), 8);
+
+        heap.collect([]);
+        assert!(heap.is_empty());
+        assert_eq!(heap.bytes_allocated(), 0);
+    }
+
+    #[test]
+    fn test_strings_are_interned() {
+        let mut heap = Heap::new();
+        let a = heap.intern("lox");
+        let b = heap.intern("lox");
+        let c = heap.intern("clox");
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(heap.len(), 2);
+        assert_eq!(Value::Obj(a), Value::Obj(b));
+    }
+
+    #[test]
+    fn test_intern_table_is_weak() {
+        let mut heap = Heap::new();
+        let kept = heap.intern("kept");
+        let garbage = heap.intern("garbage");
+
+        // 字符串表不会让字符串保持存活
+        heap.collect([Value::Obj(kept)]);
+        assert!(!is_live(&heap, garbage));
+        assert_eq!(heap.intern("kept"), kept);
+
+        // 被回收的字符串从表中移除，再次驻留时重新分配
+        assert_eq!(heap.strings.find(hash_string("garbage"), |_| true), None);
+        let again = heap.intern("garbage");
+        assert_eq!(heap.string(again), "garbage");
+        assert_eq!(heap.len(), 2);
+    }
+
+    #[test]
+    fn test_freed_slots_are_reused() {
+        let mut heap = Heap::new();
+        let garbage = heap.intern("garbage");
+        heap.collect([]);
+        let reused = heap.intern("new");
+        assert_eq!(reused, garbage);
+        assert_eq!(heap.string(reused), "new");
+    }
+
+    #[test]
+    fn test_growth_counts_towards_the_next_collection() {
+        let mut heap = Heap::new();
+        let name = heap.intern("A");
+        let class = heap.alloc(Obj::Class(ObjClass {
+            name,
+            methods: HashMap::new(),
+        }));
+        let before = heap.bytes_allocated();
+
+        // 对象分配之后的增长同样计入阈值
+        heap.grew(class, FIRST_GC);
+        assert_eq!(heap.bytes_allocated(), before + FIRST_GC);
+        assert!(heap.should_collect());
+
+        // 回收后按对象的实际大小重新计算
+        heap.collect([Value::Obj(class)]);
+        assert_eq!(heap.bytes_allocated(), before);
+    }
+
+    #[test]
+    fn test_collection_is_due_after_growth() {
+        let mut heap = Heap::new();
+        assert!(!heap.should_collect());
+        let big = heap.intern(&"x".repeat(FIRST_GC));
+        assert!(heap.should_collect());
+
+        // 回收后，下一次回收的阈值按存活大小的倍数增长
+        heap.collect([Value::Obj(big)]);
+        assert!(!heap.should_collect());
+        assert_eq!(heap.next_gc, heap.bytes_allocated() * GC_HEAP_GROW_FACTOR);
+
+        heap.set_stress(true);
+        assert!(heap.should_collect());
+    }
+}
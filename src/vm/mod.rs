@@ -0,0 +1,1178 @@
+/// Bytecode chunks and the instruction set
+mod chunk;
+/// Single-pass compiler from tokens to bytecode
+mod compiler;
+/// Disassembler for bytecode listings and execution traces
+mod debug;
+/// The object heap
+mod memory;
+/// Heap-allocated objects
+mod object;
+/// The hash table that interns strings
+mod table;
+/// Values on the virtual machine's stack
+mod value;
+
+pub use chunk::{Chunk, OpCode};
+pub use compiler::{compile, CompileError};
+use compiler::compile_with_roots;
+pub use debug::{disassemble_chunk, disassemble_function, disassemble_instruction};
+pub use memory::{Heap, ValueDisplay};
+use memory::{CAPTURE_SIZE, PROPERTY_SIZE};
+pub use object::{
+    Obj, ObjBoundMethod, ObjClass, ObjClosure, ObjFunction, ObjInstance, ObjNative, ObjRef, ObjString, ObjUpvalue,
+};
+pub use value::Value;
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    io::{self, Write},
+    rc::Rc,
+};
+
+use crate::interpreter::{self, Callable, NativeFunction, RuntimeError, StackFrame};
+
+/// The most calls that may be in progress at once
+const FRAMES_MAX: usize = 64;
+
+/// An error that stopped a program from running to completion
+/// 
+/// # Variants
+/// 
+/// * `Compile` - The program could not be compiled; nothing was run
+/// * `Runtime` - The program failed while running
+#[derive(Debug)]
+pub enum InterpretError {
+    Compile(Vec<CompileError>),
+    Runtime(RuntimeError),
+}
+
+impl fmt::Display for InterpretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterpretError::Compile(errors) => {
+                let lines: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            InterpretError::Runtime(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for InterpretError {}
+
+/// A function call that is being executed
+/// 
+/// # Fields
+/// 
+/// * `closure` - The closure being executed
+/// * `ip` - The offset of the next instruction in its function's chunk
+/// * `slots` - The stack index of the function's slot 0
+#[derive(Debug)]
+struct CallFrame {
+    closure: ObjRef,
+    ip: usize,
+    slots: usize,
+}
+
+/// A stack-based virtual machine that runs compiled Lox programs
+/// 
+/// Globals persist across calls to [`Vm::interpret`], so a REPL can run
+/// one line at a time.
+/// 
+/// # Fields
+/// 
+/// * `heap` - Every object the program has allocated
+/// * `stack` - The value stack, shared by all calls
+/// * `frames` - The calls in progress, innermost last
+/// * `globals` - The global variables, keyed by their interned names
+/// * `open_upvalues` - The upvalues that still point into the stack
+/// * `init_string` - The interned name of initializers, looked up on every
+///   instance creation
+/// * `output` - Where `print`, listings and traces write to
+/// * `disassemble` - Whether to list the bytecode of each compiled program
+/// * `trace_exec` - Whether to show the stack and each instruction as it runs
+pub struct Vm {
+    heap: Heap,
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<ObjRef, Value>,
+    open_upvalues: Vec<ObjRef>,
+    init_string: ObjRef,
+    output: Box<dyn Write>,
+    disassemble: bool,
+    trace_exec: bool,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Vm::new()
+    }
+}
+
+impl Vm {
+    /// Creates a virtual machine that prints to stdout
+    pub fn new() -> Vm {
+        Vm::with_output(Box::new(io::stdout()))
+    }
+
+    /// Creates a virtual machine that prints to the given writer
+    /// 
+    /// # Arguments
+    /// 
+    /// * `output` - Where `print` writes to
+    pub fn with_output(output: Box<dyn Write>) -> Vm {
+        let mut heap = Heap::new();
+        let init_string = heap.intern("init");
+        Vm {
+            heap,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals: HashMap::new(),
+            open_upvalues: Vec::new(),
+            init_string,
+            output,
+            disassemble: false,
+            trace_exec: false,
+        }
+    }
+
+    /// Lists the bytecode of every program before running it
+    /// 
+    /// The listing covers the top-level code and every function in it, in
+    /// the format of [`disassemble_function`].
+    pub fn set_disassemble(&mut self, enabled: bool) {
+        self.disassemble = enabled;
+    }
+
+    /// Shows the value stack and the instruction before executing each
+    /// instruction
+    pub fn set_trace_exec(&mut self, enabled: bool) {
+        self.trace_exec = enabled;
+    }
+
+    /// Collects garbage before every allocation instead of when the heap
+    /// has grown, so objects the collector misses are freed right away
+    pub fn set_stress_gc(&mut self, enabled: bool) {
+        self.heap.set_stress(enabled);
+    }
+
+    /// Logs allocations and collections to stderr
+    pub fn set_log_gc(&mut self, enabled: bool) {
+        self.heap.set_log(enabled);
+    }
+
+    /// Defines a native function as a global variable
+    /// 
+    /// # Arguments
+    /// 
+    /// * `function` - The function, registered under its own name
+    pub fn define_native(&mut self, function: Rc<NativeFunction>) {
+        let name = self.intern(function.name());
+        // keep the name on the stack while the function is allocated
+        self.push(Value::Obj(name));
+        let native = self.alloc(Obj::Native(ObjNative { function }));
+        self.pop();
+        self.globals.insert(name, Value::Obj(native));
+    }
+
+    /// Compiles and runs a program
+    /// 
+    /// # Arguments
+    /// 
+    /// * `source` - The program's source code
+    /// 
+    /// # Returns
+    /// 
+    /// * `Ok(())` - If the program ran to completion
+    /// * `Err(InterpretError)` - The compile errors, or the runtime error
+    ///   that stopped the program
+    pub fn interpret(&mut self, source: &str) -> Result<(), InterpretError> {
+        let roots: Vec<Value> = self.global_roots().collect();
+        let script = compile_with_roots(source, &mut self.heap, &roots).map_err(InterpretError::Compile)?;
+        if self.disassemble {
+            let listing = disassemble_function(script, &self.heap);
+            write!(self.output, "{}", listing).expect("failed to write program output");
+        }
+
+        // keep the function on the stack while its closure is allocated
+        self.push(Value::Obj(script));
+        let script = self.alloc(Obj::Closure(ObjClosure {
+            function: script,
+            upvalues: Vec::new(),
+        }));
+        self.pop();
+        self.push(Value::Obj(script));
+        let result = self.call(script, 0).and_then(|()| self.run());
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+        }
+        result.map_err(InterpretError::Runtime)
+    }
+
+    /// execute instructions until the top-level code returns
+    fn run(&mut self) -> Result<(), RuntimeError> {
+        loop {
+            if self.trace_exec {
+                self.trace_instruction();
+            }
+
+            let byte = self.read_byte();
+            let Some(op) = OpCode::from_byte(byte) else {
+                return Err(self.error(&format!("Unknown opcode {}.", byte)));
+            };
+
+            match op {
+                OpCode::Constant => {
+                    let constant = self.read_constant();
+                    self.push(constant);
+                }
+                OpCode::Nil => self.push(Value::Nil),
+                OpCode::True => self.push(Value::Bool(true)),
+                OpCode::False => self.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.read_byte() as usize;
+                    let value = self.stack[self.frame().slots + slot];
+                    self.push(value);
+                }
+                OpCode::SetLocal => {
+                    let slot = self.read_byte() as usize;
+                    let index = self.frame().slots + slot;
+                    self.stack[index] = self.peek(0);
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_string();
+                    match self.globals.get(&name) {
+                        Some(&value) => self.push(value),
+                        None => return Err(self.undefined_variable(name)),
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_string();
+                    let value = self.peek(0);
+                    self.globals.insert(name, value);
+                    self.pop();
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_string();
+                    let value = self.peek(0);
+                    match self.globals.get_mut(&name) {
+                        Some(global) => *global = value,
+                        None => return Err(self.undefined_variable(name)),
+                    }
+                }
+                OpCode::GetUpvalue => {
+                    let upvalue = self.read_upvalue();
+                    let value = match self.heap.upvalue(upvalue) {
+                        ObjUpvalue::Open(slot) => self.stack[slot],
+                        ObjUpvalue::Closed(value) => value,
+                    };
+                    self.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let upvalue = self.read_upvalue();
+                    let value = self.peek(0);
+                    if let Obj::Upvalue(upvalue) = self.heap.get_mut(upvalue) {
+                        match upvalue {
+                            ObjUpvalue::Open(slot) => self.stack[*slot] = value,
+                            ObjUpvalue::Closed(closed) => *closed = value,
+                        }
+                    }
+                }
+                OpCode::GetProperty => {
+                    let name = self.read_string();
+                    let Some(instance) = self.instance(self.peek(0)) else {
+                        return Err(self.error("Only instances have properties."));
+                    };
+                    if let Some(&value) = instance.fields.get(&name) {
+                        self.pop();
+                        self.push(value);
+                    } else {
+                        let class = instance.class;
+                        self.bind_method(class, name)?;
+                    }
+                }
+                OpCode::SetProperty => {
+                    let name = self.read_string();
+                    let Value::Obj(object) = self.peek(1) else {
+                        return Err(self.error("Only instances have fields."));
+                    };
+                    let value = self.peek(0);
+                    let previous = match self.heap.get_mut(object) {
+                        Obj::Instance(instance) => instance.fields.insert(name, value),
+                        _ => return Err(self.error("Only instances have fields.")),
+                    };
+                    if previous.is_none() {
+                        self.grew(object, PROPERTY_SIZE);
+                    }
+                    self.pop();
+                    self.pop();
+                    self.push(value);
+                }
+                OpCode::GetSuper => {
+                    let name = self.read_string();
+                    let Value::Obj(superclass) = self.pop() else {
+                        unreachable!("the compiler only loads classes as superclasses")
+                    };
+                    self.bind_method(superclass, name)?;
+                }
+                OpCode::Equal => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    self.push(Value::Bool(a == b));
+                }
+                OpCode::Greater => self.binary_op(|a, b| Value::Bool(a > b))?,
+                OpCode::Less => self.binary_op(|a, b| Value::Bool(a < b))?,
+                OpCode::Add => self.add()?,
+                OpCode::Subtract => self.binary_op(|a, b| Value::Number(a - b))?,
+                OpCode::Multiply => self.binary_op(|a, b| Value::Number(a * b))?,
+                OpCode::Divide => self.binary_op(|a, b| Value::Number(a / b))?,
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.push(Value::Bool(value.is_falsey()));
+                }
+                OpCode::Negate => {
+                    let Value::Number(n) = self.peek(0) else {
+                        return Err(self.error("Operand must be a number."));
+                    };
+                    self.pop();
+                    self.push(Value::Number(-n));
+                }
+                OpCode::Print => {
+                    let value = self.pop();
+                    let text = self.heap.display(value).to_string();
+                    writeln!(self.output, "{}", text).expect("failed to write program output");
+                }
+                OpCode::Jump => {
+                    let offset = self.read_short();
+                    self.frame_mut().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_short();
+                    if self.peek(0).is_falsey() {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_short();
+                    self.frame_mut().ip -= offset;
+                }
+                OpCode::Call => {
+                    let arg_count = self.read_byte() as usize;
+                    self.call_value(self.peek(arg_count), arg_count)?;
+                }
+                OpCode::Invoke => {
+                    let name = self.read_string();
+                    let arg_count = self.read_byte() as usize;
+                    self.invoke(name, arg_count)?;
+                }
+                OpCode::SuperInvoke => {
+                    let name = self.read_string();
+                    let arg_count = self.read_byte() as usize;
+                    let Value::Obj(superclass) = self.pop() else {
+                        unreachable!("the compiler only loads classes as superclasses")
+                    };
+                    self.invoke_from_class(superclass, name, arg_count)?;
+                }
+                OpCode::Closure => {
+                    let Value::Obj(function) = self.read_constant() else {
+                        unreachable!("the compiler only wraps functions in closures")
+                    };
+                    let closure = self.alloc(Obj::Closure(ObjClosure {
+                        function,
+                        upvalues: Vec::new(),
+                    }));
+                    // on the stack before capturing, so captured upvalues
+                    // are reachable while the rest are allocated
+                    self.push(Value::Obj(closure));
+                    for _ in 0..self.heap.function(function).upvalue_count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte() as usize;
+                        let upvalue = if is_local {
+                            self.capture_upvalue(self.frame().slots + index)
+                        } else {
+                            self.heap.closure(self.frame().closure).upvalues[index]
+                        };
+                        if let Obj::Closure(closure) = self.heap.get_mut(closure) {
+                            closure.upvalues.push(upvalue);
+                        }
+                        self.grew(closure, CAPTURE_SIZE);
+                    }
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("a function is always running");
+                    self.close_upvalues(frame.slots);
+                    if self.frames.is_empty() {
+                        self.pop();
+                        return Ok(());
+                    }
+                    self.stack.truncate(frame.slots);
+                    self.push(result);
+                }
+                OpCode::Class => {
+                    let name = self.read_string();
+                    let class = self.alloc(Obj::Class(ObjClass {
+                        name,
+                        methods: HashMap::new(),
+                    }));
+                    self.push(Value::Obj(class));
+                }
+                OpCode::Inherit => {
+                    let Some(superclass) = self.class(self.peek(1)) else {
+                        return Err(self.error("Superclass must be a class."));
+                    };
+                    let methods = superclass.methods.clone();
+                    if let Value::Obj(subclass) = self.peek(0) {
+                        let inherited = methods.len();
+                        if let Obj::Class(subclass) = self.heap.get_mut(subclass) {
+                            subclass.methods.extend(methods);
+                        }
+                        self.grew(subclass, inherited * PROPERTY_SIZE);
+                    }
+                    self.pop();
+                }
+                OpCode::Method => {
+                    let name = self.read_string();
+                    let method = self.peek(0);
+                    if let Value::Obj(class) = self.peek(1) {
+                        let previous = match self.heap.get_mut(class) {
+                            Obj::Class(class) => class.methods.insert(name, method),
+                            _ => unreachable!("the compiler only adds methods to classes"),
+                        };
+                        if previous.is_none() {
+                            self.grew(class, PROPERTY_SIZE);
+                        }
+                    }
+                    self.pop();
+                }
+            }
+        }
+    }
+
+    /// write the value stack and the next instruction to the output
+    fn trace_instruction(&mut self) {
+        let mut stack = String::from("          ");
+        for &value in &self.stack {
+            stack.push_str(&format!("[ {} ]", self.heap.display(value)));
+        }
+        let frame = self.frame();
+        let chunk = &self.function(frame).chunk;
+        let (instruction, _) = disassemble_instruction(chunk, &self.heap, frame.ip);
+
+        writeln!(self.output, "{}\n{}", stack, instruction).expect("failed to write program output");
+    }
+
+    // ---- calls ----
+
+    /// call a value with the arguments on top of the stack
+    fn call_value(&mut self, callee: Value, arg_count: usize) -> Result<(), RuntimeError> {
+        let Value::Obj(object) = callee else {
+            return Err(self.error("Can only call functions and classes."));
+        };
+
+        match self.heap.get(object) {
+            Obj::Closure(_) => self.call(object, arg_count),
+            Obj::Native(native) => {
+                let function = Rc::clone(&native.function);
+                self.call_native(&function, arg_count)
+            }
+            Obj::Class(class) => {
+                let initializer = class.methods.get(&self.init_string).copied();
+                let instance = self.alloc(Obj::Instance(ObjInstance {
+                    class: object,
+                    fields: HashMap::new(),
+                }));
+                let callee_slot = self.stack.len() - arg_count - 1;
+                self.stack[callee_slot] = Value::Obj(instance);
+                match initializer {
+                    Some(Value::Obj(initializer)) => self.call(initializer, arg_count),
+                    _ if arg_count != 0 => Err(self.error(&format!("Expected 0 arguments but got {}.", arg_count))),
+                    _ => Ok(()),
+                }
+            }
+            Obj::BoundMethod(bound) => {
+                let method = bound.method;
+                let callee_slot = self.stack.len() - arg_count - 1;
+                self.stack[callee_slot] = bound.receiver;
+                self.call(method, arg_count)
+            }
+            _ => Err(self.error("Can only call functions and classes.")),
+        }
+    }
+
+    /// start executing a closure whose arguments are on top of the stack
+    fn call(&mut self, closure: ObjRef, arg_count: usize) -> Result<(), RuntimeError> {
+        let arity = self.heap.function(self.heap.closure(closure).function).arity;
+        if arg_count != arity {
+            return Err(self.error(&format!("Expected {} arguments but got {}.", arity, arg_count)));
+        }
+        if self.frames.len() == FRAMES_MAX {
+            return Err(self.error("Stack overflow."));
+        }
+
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            slots: self.stack.len() - arg_count - 1,
+        });
+        Ok(())
+    }
+
+    /// call a native function, replacing it and its arguments with the result
+    /// 
+    /// Natives are shared with the tree-walking interpreter, so values are
+    /// converted to and from its representation.
+    fn call_native(&mut self, function: &NativeFunction, arg_count: usize) -> Result<(), RuntimeError> {
+        if arg_count != function.arity() {
+            return Err(self.error(&format!(
+                "Expected {} arguments but got {}.",
+                function.arity(),
+                arg_count
+            )));
+        }
+
+        let first = self.stack.len() - arg_count;
+        let arguments = self.stack[first..]
+            .iter()
+            .map(|&value| self.native_argument(value))
+            .collect::<Result<Vec<_>, _>>()?;
+        let result = function
+            .invoke(&arguments)
+            .and_then(|result| self.native_result(result))
+            .map_err(|error| self.error(&error.message))?;
+
+        self.stack.truncate(first - 1);
+        self.push(result);
+        Ok(())
+    }
+
+    /// call a method of the receiver below the arguments
+    fn invoke(&mut self, name: ObjRef, arg_count: usize) -> Result<(), RuntimeError> {
+        let Some(instance) = self.instance(self.peek(arg_count)) else {
+            return Err(self.error("Only instances have properties."));
+        };
+
+        // a field holding a function shadows a method of the same name
+        if let Some(&field) = instance.fields.get(&name) {
+            let callee_slot = self.stack.len() - arg_count - 1;
+            self.stack[callee_slot] = field;
+            return self.call_value(field, arg_count);
+        }
+
+        let class = instance.class;
+        self.invoke_from_class(class, name, arg_count)
+    }
+
+    /// call a method of a class with the receiver below the arguments
+    fn invoke_from_class(&mut self, class: ObjRef, name: ObjRef, arg_count: usize) -> Result<(), RuntimeError> {
+        match self.class(Value::Obj(class)).and_then(|class| class.methods.get(&name)) {
+            Some(&Value::Obj(method)) => self.call(method, arg_count),
+            _ => Err(self.undefined_property(name)),
+        }
+    }
+
+    // ---- memory ----
+
+    /// move an object onto the heap, collecting garbage first if due
+    /// 
+    /// Anything the new object refers to must already be reachable from
+    /// the roots, typically by being on the stack.
+    fn alloc(&mut self, object: Obj) -> ObjRef {
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+        self.heap.alloc(object)
+    }
+
+    /// record that an object grew, collecting garbage if that made a
+    /// collection due
+    /// 
+    /// The object and everything it now refers to must be reachable from
+    /// the roots.
+    fn grew(&mut self, object: ObjRef, bytes: usize) {
+        self.heap.grew(object, bytes);
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+    }
+
+    /// intern a string, collecting garbage first if due
+    fn intern(&mut self, chars: &str) -> ObjRef {
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+        self.heap.intern(chars)
+    }
+
+    /// free every object the program can no longer reach
+    /// 
+    /// The roots are the value stack, the closures being executed, the
+    /// globals and their names, the open upvalues and `init_string`.
+    fn collect_garbage(&mut self) {
+        let stack = self.stack.iter().copied();
+        let frames = self.frames.iter().map(|frame| Value::Obj(frame.closure));
+        let open_upvalues = self.open_upvalues.iter().map(|&upvalue| Value::Obj(upvalue));
+        let globals = self.global_roots();
+        let roots: Vec<Value> = stack.chain(frames).chain(open_upvalues).chain(globals).collect();
+        self.heap.collect(roots);
+    }
+
+    /// the roots that outlive a single program: the globals, their names
+    /// and `init_string`
+    fn global_roots(&self) -> impl Iterator<Item = Value> + '_ {
+        let globals = self.globals.iter().flat_map(|(&name, &value)| [Value::Obj(name), value]);
+        globals.chain([Value::Obj(self.init_string)])
+    }
+
+    // ---- upvalues ----
+
+    /// the upvalue for a stack slot, reusing one that is already open so
+    /// that closures capturing the same variable share it
+    fn capture_upvalue(&mut self, slot: usize) -> ObjRef {
+        let existing = self
+            .open_upvalues
+            .iter()
+            .find(|&&upvalue| matches!(self.heap.upvalue(upvalue), ObjUpvalue::Open(open) if open == slot));
+        if let Some(&upvalue) = existing {
+            return upvalue;
+        }
+
+        let upvalue = self.alloc(Obj::Upvalue(ObjUpvalue::Open(slot)));
+        self.open_upvalues.push(upvalue);
+        upvalue
+    }
+
+    /// close every open upvalue for the stack slot `last` or above, moving
+    /// the variables out of the stack
+    fn close_upvalues(&mut self, last: usize) {
+        let stack = &self.stack;
+        let heap = &mut self.heap;
+        self.open_upvalues.retain(|&upvalue| match heap.get_mut(upvalue) {
+            Obj::Upvalue(upvalue) => match *upvalue {
+                ObjUpvalue::Open(slot) if slot >= last => {
+                    *upvalue = ObjUpvalue::Closed(stack[slot]);
+                    false
+                }
+                _ => true,
+            },
+            _ => true,
+        });
+    }
+
+    /// replace the instance on top of the stack with one of its class's
+    /// methods bound to it
+    fn bind_method(&mut self, class: ObjRef, name: ObjRef) -> Result<(), RuntimeError> {
+        let Some(&Value::Obj(method)) = self.class(Value::Obj(class)).and_then(|class| class.methods.get(&name)) else {
+            return Err(self.undefined_property(name));
+        };
+
+        let bound = self.alloc(Obj::BoundMethod(ObjBoundMethod {
+            receiver: self.peek(0),
+            method,
+        }));
+        self.pop();
+        self.push(Value::Obj(bound));
+        Ok(())
+    }
+
+    // ---- operators ----
+
+    /// apply a numeric operator to the top two values
+    fn binary_op(&mut self, op: impl Fn(f64, f64) -> Value) -> Result<(), RuntimeError> {
+        let (Value::Number(a), Value::Number(b)) = (self.peek(1), self.peek(0)) else {
+            return Err(self.error("Operands must be numbers."));
+        };
+        self.pop();
+        self.pop();
+        self.push(op(a, b));
+        Ok(())
+    }
+
+    /// add two numbers or concatenate two strings
+    fn add(&mut self) -> Result<(), RuntimeError> {
+        let (a, b) = (self.peek(1), self.peek(0));
+        let result = match (a, b) {
+            (Value::Number(a), Value::Number(b)) => Value::Number(a + b),
+            (Value::Obj(x), Value::Obj(y)) if self.heap.is_string(a) && self.heap.is_string(b) => {
+                let chars = format!("{}{}", self.heap.string(x), self.heap.string(y));
+                Value::Obj(self.intern(&chars))
+            }
+            _ => return Err(self.error("Operands must be two numbers or two strings.")),
+        };
+        self.pop();
+        self.pop();
+        self.push(result);
+        Ok(())
+    }
+
+    // ---- conversions for native functions ----
+
+    /// convert a value into an argument for a native function
+    fn native_argument(&self, value: Value) -> Result<interpreter::Value, RuntimeError> {
+        match value {
+            Value::Nil => Ok(interpreter::Value::Nil),
+            Value::Bool(b) => Ok(interpreter::Value::Bool(b)),
+            Value::Number(n) => Ok(interpreter::Value::Number(n)),
+            Value::Obj(object) => match self.heap.get(object) {
+                Obj::String(string) => Ok(interpreter::Value::String(string.chars.as_ref().into())),
+                _ => Err(self.error("Native functions only take nil, booleans, numbers and strings.")),
+            },
+        }
+    }
+
+    /// convert a native function's result
+    fn native_result(&mut self, value: interpreter::Value) -> Result<Value, RuntimeError> {
+        match value {
+            interpreter::Value::Nil => Ok(Value::Nil),
+            interpreter::Value::Bool(b) => Ok(Value::Bool(b)),
+            interpreter::Value::Number(n) => Ok(Value::Number(n)),
+            interpreter::Value::String(s) => Ok(Value::Obj(self.intern(&s))),
+            _ => Err(self.error("Native functions can only return nil, booleans, numbers and strings.")),
+        }
+    }
+
+    // ---- reading the stack and the code ----
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("the compiler keeps the stack balanced")
+    }
+
+    /// the value `distance` slots below the top of the stack
+    fn peek(&self, distance: usize) -> Value {
+        self.stack[self.stack.len() - 1 - distance]
+    }
+
+    /// the instance a value refers to, if it is one
+    fn instance(&self, value: Value) -> Option<&ObjInstance> {
+        match value {
+            Value::Obj(object) => match self.heap.get(object) {
+                Obj::Instance(instance) => Some(instance),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// the class a value refers to, if it is one
+    fn class(&self, value: Value) -> Option<&ObjClass> {
+        match value {
+            Value::Obj(object) => match self.heap.get(object) {
+                Obj::Class(class) => Some(class),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("a function is always running")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("a function is always running")
+    }
+
+    /// the function the current frame is executing
+    fn function(&self, frame: &CallFrame) -> &ObjFunction {
+        self.heap.function(self.heap.closure(frame.closure).function)
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frames.last_mut().expect("a function is always running");
+        let function = self.heap.closure(frame.closure).function;
+        let byte = self.heap.function(function).chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    /// read a two-byte big-endian operand
+    fn read_short(&mut self) -> usize {
+        let high = self.read_byte();
+        let low = self.read_byte();
+        u16::from_be_bytes([high, low]) as usize
+    }
+
+    fn read_constant(&mut self) -> Value {
+        let index = self.read_byte() as usize;
+        self.function(self.frame()).chunk.constants[index]
+    }
+
+    /// read an upvalue index and return that upvalue of the current closure
+    fn read_upvalue(&mut self) -> ObjRef {
+        let index = self.read_byte() as usize;
+        self.heap.closure(self.frame().closure).upvalues[index]
+    }
+
+    /// read a constant that the compiler guarantees is a string
+    fn read_string(&mut self) -> ObjRef {
+        match self.read_constant() {
+            Value::Obj(name) => name,
+            other => unreachable!("expected a string constant, found {:?}", other),
+        }
+    }
+
+    // ---- errors ----
+
+    /// build a runtime error at the current instruction, with a stack trace
+    fn error(&self, message: &str) -> RuntimeError {
+        let trace: Vec<StackFrame> = self
+            .frames
+            .iter()
+            .rev()
+            .map(|frame| {
+                let function = self.function(frame);
+                StackFrame {
+                    function: function.name.map(|name| self.heap.string(name).to_string()),
+                    line: function.chunk.line(frame.ip.saturating_sub(1)),
+                }
+            })
+            .collect();
+        RuntimeError {
+            line: trace.first().map_or(0, |frame| frame.line),
+            message: message.to_string(),
+            trace,
+        }
+    }
+
+    fn undefined_variable(&self, name: ObjRef) -> RuntimeError {
+        self.error(&format!("Undefined variable '{}'.", self.heap.string(name)))
+    }
+
+    fn undefined_property(&self, name: ObjRef) -> RuntimeError {
+        self.error(&format!("Undefined property '{}'.", self.heap.string(name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    // 辅助结构：可以在测试中读取的输出缓冲区
+    #[derive(Clone, Default)]
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // 辅助函数：运行程序，返回打印的输出
+    fn output(source: &str) -> String {
+        let buffer = SharedOutput::default();
+        let mut vm = Vm::with_output(Box::new(buffer.clone()));
+        vm.interpret(source).expect("程序应该正常运行");
+        let output = buffer.0.borrow();
+        String::from_utf8(output.clone()).unwrap()
+    }
+
+    // 辅助函数：运行程序，返回运行时错误
+    fn runtime_error(source: &str) -> RuntimeError {
+        let mut vm = Vm::with_output(Box::new(io::sink()));
+        match vm.interpret(source) {
+            Err(InterpretError::Runtime(error)) => error,
+            other => panic!("应该产生运行时错误，实际为 {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(output("print 1 + 2 * 3 - 4 / 2;"), "5\n");
+        assert_eq!(output("print -(1 + 2);"), "-3\n");
+        assert_eq!(output("print 1 < 2 == !(2 <= 1);"), "true\n");
+    }
+
+    #[test]
+    fn test_strings() {
+        assert_eq!(output("print \"a\" + \"b\" == \"ab\";"), "true\n");
+        assert_eq!(output("var s = \"lox\"; print s + s;"), "loxlox\n");
+    }
+
+    #[test]
+    fn test_strings_are_interned() {
+        let mut vm = Vm::with_output(Box::new(io::sink()));
+        vm.interpret("var a = \"ab\"; var b = \"a\" + \"b\"; var c = \"a\" + \"b\";").unwrap();
+        // 字面量和拼接结果是同一个字符串对象
+        let global = |vm: &mut Vm, name: &str| {
+            let name = vm.heap.intern(name);
+            vm.globals[&name]
+        };
+        let a = global(&mut vm, "a");
+        assert_eq!(global(&mut vm, "b"), a);
+        assert_eq!(global(&mut vm, "c"), a);
+    }
+
+    #[test]
+    fn test_fields_and_methods_are_found_by_interned_name() {
+        let source = "class A { init() { this.f = \"field\"; } m() { return \"method\"; } }
+var a = A();
+a.g = a.m;
+print a.f + \" \" + a.g();";
+        assert_eq!(output(source), "field method\n");
+    }
+
+    #[test]
+    fn test_variables_and_scopes() {
+        assert_eq!(output("var a = 1; { var a = 2; print a; } print a;"), "2\n1\n");
+        assert_eq!(output("var a = 1; { var b = a + 1; a = b; } print a;"), "2\n");
+    }
+
+    #[test]
+    fn test_control_flow() {
+        assert_eq!(output("for (var i = 0; i < 3; i = i + 1) print i;"), "0\n1\n2\n");
+        assert_eq!(output("if (nil or 0) print \"yes\"; else print \"no\";"), "yes\n");
+        assert_eq!(output("var i = 0; while (i < 2) { print i; i = i + 1; }"), "0\n1\n");
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(
+            output("fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); } print fib(10);"),
+            "55\n"
+        );
+        assert_eq!(output("fun f() {} print f(); print f;"), "nil\n<fn f>\n");
+    }
+
+    #[test]
+    fn test_classes() {
+        let source = "
+            class Counter {
+                init(start) { this.count = start; }
+                increment() { this.count = this.count + 1; return this; }
+            }
+            var counter = Counter(1);
+            print counter.increment().increment().count;
+            var method = counter.increment;
+            print method().count;
+            print counter;
+            print Counter;
+        ";
+        assert_eq!(output(source), "3\n4\nCounter instance\nCounter\n");
+    }
+
+    #[test]
+    fn test_inheritance() {
+        let source = "
+            class A { name() { return \"A\"; } }
+            class B < A {}
+            print B().name();
+        ";
+        assert_eq!(output(source), "A\n");
+    }
+
+    #[test]
+    fn test_closures_capture_variables() {
+        let source = "
+            fun makeCounter() {
+                var i = 0;
+                fun count() { i = i + 1; return i; }
+                return count;
+            }
+            var a = makeCounter();
+            var b = makeCounter();
+            print a(); print a(); print b();
+        ";
+        assert_eq!(output(source), "1\n2\n1\n");
+    }
+
+    #[test]
+    fn test_closures_share_captured_variables() {
+        let source = "
+            var get; var set;
+            {
+                var value = \"before\";
+                fun getter() { return value; }
+                fun setter(v) { value = v; }
+                get = getter; set = setter;
+                set(\"open\");
+                print value;
+            }
+            print get();
+            set(\"closed\");
+            print get();
+        ";
+        assert_eq!(output(source), "open\nopen\nclosed\n");
+    }
+
+    #[test]
+    fn test_closures_capture_each_loop_iteration() {
+        let source = "
+            var first; var second;
+            for (var i = 1; i <= 2; i = i + 1) {
+                var j = i;
+                fun show() { print j; }
+                if (i == 1) first = show; else second = show;
+            }
+            first(); second();
+        ";
+        assert_eq!(output(source), "1\n2\n");
+    }
+
+    #[test]
+    fn test_super_calls() {
+        let source = "
+            class A { greet(name) { return \"A \" + name; } }
+            class B < A {
+                greet(name) { return \"B \" + super.greet(name); }
+                method() { return super.greet; }
+            }
+            print B().greet(\"x\");
+            print B().method()(\"y\");
+        ";
+        assert_eq!(output(source), "B A x\nA y\n");
+    }
+
+    #[test]
+    fn test_stress_gc_keeps_live_objects() {
+        let buffer = SharedOutput::default();
+        let mut vm = Vm::with_output(Box::new(buffer.clone()));
+        vm.set_stress_gc(true);
+        let source = "
+            class Node { init(value, next) { this.value = value; this.next = next; } }
+            fun makeAdder(n) { fun add(x) { return x + n; } return add; }
+            var list = nil;
+            for (var i = 0; i < 5; i = i + 1) {
+                list = Node(makeAdder(i)(1), list);
+            }
+            while (list != nil) { print list.value; list = list.next; }
+        ";
+        vm.interpret(source).unwrap();
+        let output = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        assert_eq!(output, "5\n4\n3\n2\n1\n");
+    }
+
+    #[test]
+    fn test_garbage_is_collected() {
+        let mut vm = Vm::with_output(Box::new(io::sink()));
+        vm.set_stress_gc(true);
+        vm.interpret("var s = \"\"; for (var i = 0; i < 100; i = i + 1) { s = s + \"x\"; }").unwrap();
+        // 中间结果都是垃圾，每次分配前回收，堆的大小保持不变
+        assert!(vm.heap.len() < 20, "堆中还有 {} 个对象", vm.heap.len());
+    }
+
+    #[test]
+    fn test_collection_starts_at_threshold() {
+        let mut vm = Vm::with_output(Box::new(io::sink()));
+        let source = "class A {} for (var i = 0; i < 20000; i = i + 1) { A(); }";
+        vm.interpret(source).unwrap();
+        assert!(vm.heap.len() < 20000, "堆中还有 {} 个对象", vm.heap.len());
+    }
+
+    #[test]
+    fn test_globals_persist_between_runs() {
+        let buffer = SharedOutput::default();
+        let mut vm = Vm::with_output(Box::new(buffer.clone()));
+        vm.interpret("var a = 1;").unwrap();
+        vm.interpret("print a + 1;").unwrap();
+        assert_eq!(buffer.0.borrow().as_slice(), b"2\n");
+    }
+
+    #[test]
+    fn test_native_functions() {
+        let buffer = SharedOutput::default();
+        let mut vm = Vm::with_output(Box::new(buffer.clone()));
+        vm.define_native(Rc::new(NativeFunction::new("twice", 1, |arguments| match arguments {
+            [interpreter::Value::String(s)] => Ok(interpreter::Value::String(format!("{}{}", s, s).into())),
+            _ => Err(RuntimeError::native("Argument must be a string.")),
+        })));
+        vm.interpret("print twice(\"ab\");").unwrap();
+        assert_eq!(buffer.0.borrow().as_slice(), b"abab\n");
+
+        let error = match vm.interpret("\ntwice(1);") {
+            Err(InterpretError::Runtime(error)) => error,
+            other => panic!("应该产生运行时错误，实际为 {:?}", other),
+        };
+        assert_eq!((error.line, error.message.as_str()), (2, "Argument must be a string."));
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        let error = runtime_error("print 1;\nprint -\"a\";");
+        assert_eq!((error.line, error.message.as_str()), (2, "Operand must be a number."));
+        let error = runtime_error("print 1 + nil;");
+        assert_eq!(error.message, "Operands must be two numbers or two strings.");
+        let error = runtime_error("print nope;");
+        assert_eq!(error.message, "Undefined variable 'nope'.");
+        let error = runtime_error("fun f(a) {} f();");
+        assert_eq!(error.message, "Expected 1 arguments but got 0.");
+        let error = runtime_error("\"f\"();");
+        assert_eq!(error.message, "Can only call functions and classes.");
+        let error = runtime_error("class A {} A().b;");
+        assert_eq!(error.message, "Undefined property 'b'.");
+        let error = runtime_error("fun f() { f(); } f();");
+        assert_eq!(error.message, "Stack overflow.");
+    }
+
+    #[test]
+    fn test_stack_trace() {
+        let error = runtime_error("fun inner() {\n  return -nil;\n}\nfun outer() {\n  inner();\n}\nouter();");
+        let trace: Vec<String> = error.trace.iter().map(|frame| frame.to_string()).collect();
+        assert_eq!(trace, ["[line 2] in inner()", "[line 5] in outer()", "[line 7] in script"]);
+    }
+
+    #[test]
+    fn test_trace_exec() {
+        let buffer = SharedOutput::default();
+        let mut vm = Vm::with_output(Box::new(buffer.clone()));
+        vm.set_trace_exec(true);
+        vm.interpret("print 1 + 2;").unwrap();
+        let output = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        let expected = [
+            "          [ <script> ]",
+            "0000    1 OP_CONSTANT         0 '1'",
+            "          [ <script> ][ 1 ]",
+            "0002    | OP_CONSTANT         1 '2'",
+            "          [ <script> ][ 1 ][ 2 ]",
+            "0004    | OP_ADD",
+            "          [ <script> ][ 3 ]",
+            "0005    | OP_PRINT",
+            "3",
+            "          [ <script> ]",
+            "0006    | OP_NIL",
+            "          [ <script> ][ nil ]",
+            "0007    | OP_RETURN",
+        ];
+        let output: Vec<&str> = output.lines().collect();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_disassemble_before_running() {
+        let buffer = SharedOutput::default();
+        let mut vm = Vm::with_output(Box::new(buffer.clone()));
+        vm.set_disassemble(true);
+        vm.interpret("print nil;").unwrap();
+        let output = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        assert_eq!(output, "== <script> ==\n0000    1 OP_NIL\n0001    | OP_PRINT\n0002    | OP_NIL\n0003    | OP_RETURN\nnil\n");
+    }
+
+    #[test]
+    fn test_recovers_after_runtime_error() {
+        let buffer = SharedOutput::default();
+        let mut vm = Vm::with_output(Box::new(buffer.clone()));
+        assert!(vm.interpret("fun f() { return nil + 1; } f();").is_err());
+        vm.interpret("print 1;").unwrap();
+        assert_eq!(buffer.0.borrow().as_slice(), b"1\n");
+    }
+}
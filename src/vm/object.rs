@@ -0,0 +1,150 @@
+use std::{collections::HashMap, rc::Rc};
+
+use super::{Chunk, Value};
+use crate::interpreter::NativeFunction;
+
+/// A handle to an object on the [`Heap`](super::Heap)
+/// 
+/// Handles are compared by identity: two handles are equal only if they
+/// refer to the same object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjRef(pub(super) usize);
+
+/// An object that lives on the heap
+/// 
+/// # Variants
+/// 
+/// * `String` - An immutable string
+/// * `Function` - A compiled function
+/// * `Closure` - A function together with the variables it captured
+/// * `Upvalue` - A variable captured by a closure
+/// * `Native` - A function implemented in Rust
+/// * `Class` - A class and its methods
+/// * `Instance` - An instance of a class
+/// * `BoundMethod` - A method together with the instance it was read from
+#[derive(Debug)]
+pub enum Obj {
+    String(ObjString),
+    Function(ObjFunction),
+    Closure(ObjClosure),
+    Upvalue(ObjUpvalue),
+    Native(ObjNative),
+    Class(ObjClass),
+    Instance(ObjInstance),
+    BoundMethod(ObjBoundMethod),
+}
+
+/// A Lox string
+/// 
+/// Strings are interned by the [`Heap`](super::Heap): there is never more
+/// than one string object with the same contents, so strings can be
+/// compared by handle.
+/// 
+/// # Fields
+/// 
+/// * `chars` - The string's contents
+/// * `hash` - The FNV-1a hash of the contents
+#[derive(Debug)]
+pub struct ObjString {
+    pub chars: Box<str>,
+    pub hash: u32,
+}
+
+/// A compiled function
+/// 
+/// # Fields
+/// 
+/// * `arity` - The number of parameters
+/// * `upvalue_count` - The number of variables its closures capture
+/// * `chunk` - The function's bytecode
+/// * `name` - The function's name, or `None` for top-level code
+#[derive(Debug, Default)]
+pub struct ObjFunction {
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+    pub name: Option<ObjRef>,
+}
+
+/// A function as a runtime value, with the variables it captured
+/// 
+/// # Fields
+/// 
+/// * `function` - The compiled function
+/// * `upvalues` - The captured variables, in the order the function's
+///   `GetUpvalue` and `SetUpvalue` instructions number them
+#[derive(Debug)]
+pub struct ObjClosure {
+    pub function: ObjRef,
+    pub upvalues: Vec<ObjRef>,
+}
+
+/// A variable captured by one or more closures
+/// 
+/// While the variable is still on the stack the upvalue is open and
+/// refers to its slot, so writes through the stack and through closures
+/// are seen by both. When the variable goes out of scope its value moves
+/// into the upvalue, which is then closed.
+/// 
+/// # Variants
+/// 
+/// * `Open` - The variable lives in the stack slot at this index
+/// * `Closed` - The variable has left the stack and lives here
+#[derive(Debug, Clone, Copy)]
+pub enum ObjUpvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+/// A native function registered by the host
+/// 
+/// # Fields
+/// 
+/// * `function` - The native function, shared with the tree-walking interpreter
+#[derive(Clone)]
+pub struct ObjNative {
+    pub function: Rc<NativeFunction>,
+}
+
+impl std::fmt::Debug for ObjNative {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ObjNative({})", self.function.name())
+    }
+}
+
+/// A class
+/// 
+/// # Fields
+/// 
+/// * `name` - The class name
+/// * `methods` - The class's methods, including inherited ones, keyed by
+///   their interned names
+#[derive(Debug)]
+pub struct ObjClass {
+    pub name: ObjRef,
+    pub methods: HashMap<ObjRef, Value>,
+}
+
+/// An instance of a class
+/// 
+/// # Fields
+/// 
+/// * `class` - The class the instance was created from
+/// * `fields` - The instance's fields, keyed by their interned names
+#[derive(Debug)]
+pub struct ObjInstance {
+    pub class: ObjRef,
+    pub fields: HashMap<ObjRef, Value>,
+}
+
+/// A method read from an instance, remembering the instance
+/// 
+/// # Fields
+/// 
+/// * `receiver` - The instance `this` refers to
+/// * `method` - The method's closure
+#[derive(Debug)]
+pub struct ObjBoundMethod {
+    pub receiver: Value,
+    pub method: ObjRef,
+}
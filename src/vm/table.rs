@@ -0,0 +1,240 @@
+use super::ObjRef;
+
+/// The most a table may fill up before it grows, as a fraction
+/// `TABLE_MAX_LOAD_NUMERATOR / TABLE_MAX_LOAD_DENOMINATOR` of its capacity
+const TABLE_MAX_LOAD_NUMERATOR: usize = 3;
+const TABLE_MAX_LOAD_DENOMINATOR: usize = 4;
+/// The capacity of a table the first time it grows
+const TABLE_MIN_CAPACITY: usize = 8;
+
+/// Hashes a string with 32-bit FNV-1a
+/// 
+/// # Arguments
+/// 
+/// * `chars` - The string to hash
+/// 
+/// # Returns
+/// 
+/// The hash, which string objects cache so it is computed only once
+pub fn hash_string(chars: &str) -> u32 {
+    let mut hash: u32 = 2166136261;
+    for byte in chars.bytes() {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(16777619);
+    }
+    hash
+}
+
+/// A slot in a [`Table`]
+/// 
+/// # Variants
+/// 
+/// * `Empty` - A slot that has never held a string; probing stops here
+/// * `Tombstone` - A slot whose string was removed; probing continues past
+///   it so strings inserted after a collision can still be found
+/// * `Full` - A slot holding a string and its hash
+#[derive(Debug, Clone, Copy)]
+enum Entry {
+    Empty,
+    Tombstone,
+    Full { key: ObjRef, hash: u32 },
+}
+
+/// The set of interned strings
+/// 
+/// An open-addressing hash table: a string goes in the slot its hash
+/// selects, or in the next free slot after it, wrapping around at the end
+/// (linear probing). Removed strings leave tombstones behind so that the
+/// strings probed past them stay reachable.
+/// 
+/// The table only stores handles. Comparing contents is left to the
+/// caller, which owns the strings.
+/// 
+/// # Fields
+/// 
+/// * `entries` - The slots; the capacity is zero or a power of two
+/// * `count` - The number of full slots and tombstones, which both
+///   lengthen probe sequences and so both count towards the load
+#[derive(Debug, Default)]
+pub struct Table {
+    entries: Vec<Entry>,
+    count: usize,
+}
+
+impl Table {
+    /// Creates an empty table without allocating
+    pub fn new() -> Table {
+        Table::default()
+    }
+
+    /// Looks up a string by its contents
+    /// 
+    /// # Arguments
+    /// 
+    /// * `hash` - The hash of the contents
+    /// * `matches` - Whether a string in the table has the contents; only
+    ///   called for strings with the same hash
+    /// 
+    /// # Returns
+    /// 
+    /// The interned string, or `None` if there is none with the contents
+    pub fn find(&self, hash: u32, matches: impl Fn(ObjRef) -> bool) -> Option<ObjRef> {
+        if self.entries.is_empty() {
+            return None;
+        }
+
+        let mask = self.entries.len() - 1;
+        let mut index = hash as usize & mask;
+        loop {
+            match self.entries[index] {
+                Entry::Empty => return None,
+                Entry::Full { key, hash: full_hash } if full_hash == hash && matches(key) => {
+                    return Some(key);
+                }
+                Entry::Tombstone | Entry::Full { .. } => (),
+            }
+            index = (index + 1) & mask;
+        }
+    }
+
+    /// Adds a string that is not in the table yet
+    /// 
+    /// # Arguments
+    /// 
+    /// * `key` - The string
+    /// * `hash` - The hash of its contents
+    pub fn insert(&mut self, key: ObjRef, hash: u32) {
+        let load = (self.count + 1) * TABLE_MAX_LOAD_DENOMINATOR;
+        if load > self.entries.len() * TABLE_MAX_LOAD_NUMERATOR {
+            self.grow();
+        }
+
+        let index = self.free_slot(hash);
+        if let Entry::Empty = self.entries[index] {
+            self.count += 1;
+        }
+        self.entries[index] = Entry::Full { key, hash };
+    }
+
+    /// Removes every string `keep` returns false for, leaving tombstones
+    /// 
+    /// The garbage collector calls this before sweeping, so the table does
+    /// not keep strings alive on its own.
+    pub fn retain(&mut self, keep: impl Fn(ObjRef) -> bool) {
+        for entry in &mut self.entries {
+            if let Entry::Full { key, .. } = *entry {
+                if !keep(key) {
+                    *entry = Entry::Tombstone;
+                }
+            }
+        }
+    }
+
+    /// the first tombstone or empty slot in a hash's probe sequence
+    fn free_slot(&self, hash: u32) -> usize {
+        let mask = self.entries.len() - 1;
+        let mut index = hash as usize & mask;
+        loop {
+            match self.entries[index] {
+                Entry::Empty | Entry::Tombstone => return index,
+                Entry::Full { .. } => index = (index + 1) & mask,
+            }
+        }
+    }
+
+    /// double the capacity and reinsert the strings, dropping tombstones
+    fn grow(&mut self) {
+        let capacity = (self.entries.len() * 2).max(TABLE_MIN_CAPACITY);
+        let old = std::mem::replace(&mut self.entries, vec![Entry::Empty; capacity]);
+        self.count = 0;
+        for entry in old {
+            if let Entry::Full { key, hash } = entry {
+                let index = self.free_slot(hash);
+                self.entries[index] = Entry::Full { key, hash };
+                self.count += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 辅助函数：统计表中的字符串数量
+    fn len(table: &Table) -> usize {
+        table.entries.iter().filter(|entry| matches!(entry, Entry::Full { .. })).count()
+    }
+
+    #[test]
+    fn test_fnv1a_hash() {
+        // FNV-1a 的标准测试向量
+        assert_eq!(hash_string(""), 0x811c9dc5);
+        assert_eq!(hash_string("a"), 0xe40c292c);
+        assert_eq!(hash_string("foobar"), 0xbf9cf968);
+    }
+
+    #[test]
+    fn test_insert_and_find() {
+        let mut table = Table::new();
+        assert_eq!(table.find(1, |_| true), None);
+
+        for n in 0..100 {
+            table.insert(ObjRef(n), n as u32);
+        }
+        assert_eq!(len(&table), 100);
+        assert!(table.entries.len().is_power_of_two());
+        assert!(table.count * 4 <= table.entries.len() * 3);
+        for n in 0..100 {
+            assert_eq!(table.find(n as u32, |key| key == ObjRef(n)), Some(ObjRef(n)));
+        }
+    }
+
+    #[test]
+    fn test_colliding_hashes_are_probed() {
+        let mut table = Table::new();
+        // 三个字符串的哈希值相同，依次放入相邻的槽位
+        for n in 0..3 {
+            table.insert(ObjRef(n), 7);
+        }
+        for n in 0..3 {
+            assert_eq!(table.find(7, |key| key == ObjRef(n)), Some(ObjRef(n)));
+        }
+        assert_eq!(table.find(7, |_| false), None);
+    }
+
+    #[test]
+    fn test_tombstones_keep_probe_sequences_intact() {
+        let mut table = Table::new();
+        for n in 0..3 {
+            table.insert(ObjRef(n), 7);
+        }
+
+        // 删除中间的字符串后，之后的字符串仍然可以找到
+        table.retain(|key| key != ObjRef(1));
+        assert_eq!(len(&table), 2);
+        assert_eq!(table.find(7, |key| key == ObjRef(1)), None);
+        assert_eq!(table.find(7, |key| key == ObjRef(2)), Some(ObjRef(2)));
+
+        // 墓碑可以被复用，并且仍然计入负载
+        let count = table.count;
+        table.insert(ObjRef(3), 7);
+        assert_eq!(table.count, count);
+        assert_eq!(table.find(7, |key| key == ObjRef(3)), Some(ObjRef(3)));
+    }
+
+    #[test]
+    fn test_growing_drops_tombstones() {
+        let mut table = Table::new();
+        for n in 0..6 {
+            table.insert(ObjRef(n), n as u32);
+        }
+        table.retain(|key| key.0 % 2 == 0);
+        assert_eq!(table.count, 6);
+
+        table.insert(ObjRef(6), 6);
+        assert_eq!(table.entries.len(), 16);
+        assert_eq!(table.count, 4);
+        assert_eq!(len(&table), 4);
+    }
+}
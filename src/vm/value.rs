@@ -0,0 +1,33 @@
+use super::ObjRef;
+
+/// A value on the virtual machine's stack
+/// 
+/// Values are small and copied freely; anything larger lives on the
+/// [`Heap`](super::Heap) and is referred to by an [`ObjRef`].
+/// 
+/// # Variants
+/// 
+/// * `Nil` - The absence of a value
+/// * `Bool` - `true` or `false`
+/// * `Number` - A double-precision floating point number
+/// * `Obj` - A heap object, such as a string or a function
+/// 
+/// Equality is Lox equality: values of different types are never equal,
+/// numbers follow IEEE 754 and objects compare by identity. Strings are
+/// interned, so equal strings are the same object.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    Obj(ObjRef),
+}
+
+impl Value {
+    /// Checks whether the value counts as false in a condition
+    /// 
+    /// Only `nil` and `false` are falsey.
+    pub fn is_falsey(self) -> bool {
+        matches!(self, Value::Nil | Value::Bool(false))
+    }
+}
@@ -0,0 +1,184 @@
+//! Runs every Lox program under `test/` and checks its output
+//!
+//! A program states what it should do in comments:
+//!
+//! * `// expect: text` - a line `print` should write to stdout
+//! * `// expect runtime error: message` - the runtime error that ends the
+//!   program, which must exit with code 70
+//! * `// expect trace: [line N] in f()` - a line of that error's stack
+//!   trace; when given, the whole trace must match
+//! * `// expect error: text` - a line reported on stderr for a static
+//!   error, which stops the program from running and exits with code 65
+//! * `// skip vm: reason` - don't run the program with the given backend
+//!
+//! Every program runs with each backend, `tree` and `vm`, and once more on
+//! the `vm` backend with `--stress-gc` so that objects the garbage
+//! collector frees too early show up as failures. Programs without any
+//! expectation are skipped.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// the backends every program runs with, and the flags to run them with
+const BACKENDS: [(&str, &[&str]); 3] = [
+    ("tree", &["--backend=tree"]),
+    ("vm", &["--backend=vm"]),
+    ("vm", &["--backend=vm", "--stress-gc"]),
+];
+
+/// what a test program expects to happen
+#[derive(Debug, Default)]
+struct Expectation {
+    output: Vec<String>,
+    runtime_error: Option<String>,
+    trace: Vec<String>,
+    errors: Vec<String>,
+    skipped: Vec<String>,
+}
+
+impl Expectation {
+    fn parse(source: &str) -> Option<Expectation> {
+        let mut expectation = Expectation::default();
+        for line in source.lines() {
+            if let Some((_, text)) = line.split_once("// expect: ") {
+                expectation.output.push(text.to_string());
+            } else if let Some((_, message)) = line.split_once("// expect runtime error: ") {
+                expectation.runtime_error = Some(message.to_string());
+            } else if let Some((_, frame)) = line.split_once("// expect trace: ") {
+                expectation.trace.push(frame.to_string());
+            } else if let Some((_, text)) = line.split_once("// expect error: ") {
+                expectation.errors.push(text.to_string());
+            } else if let Some((_, skip)) = line.split_once("// skip ") {
+                if let Some((backend, _reason)) = skip.split_once(':') {
+                    expectation.skipped.push(backend.to_string());
+                }
+            }
+        }
+        if expectation.output.is_empty() && expectation.runtime_error.is_none() && expectation.errors.is_empty() {
+            None
+        } else {
+            Some(expectation)
+        }
+    }
+}
+
+// 辅助函数：递归收集目录下所有的 .lox 文件
+fn lox_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            lox_files(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "lox") {
+            files.push(path);
+        }
+    }
+}
+
+// 辅助函数：用指定的命令行参数运行一个测试程序，返回失败原因
+fn check(path: &Path, flags: &[&str], expectation: &Expectation) -> Result<(), String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .args(flags)
+        .arg(path)
+        .output()
+        .map_err(|error| error.to_string())?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let actual: Vec<&str> = stdout.lines().collect();
+    if actual != expectation.output {
+        return Err(format!("expected output {:?}, got {:?}", expectation.output, actual));
+    }
+
+    if !expectation.errors.is_empty() {
+        let actual: Vec<&str> = stderr.lines().collect();
+        if actual != expectation.errors {
+            return Err(format!("expected errors {:?}, got {:?}", expectation.errors, actual));
+        }
+        if output.status.code() != Some(65) {
+            return Err(format!("expected exit code 65, got {:?}", output.status.code()));
+        }
+        return Ok(());
+    }
+
+    let (code, message) = match &expectation.runtime_error {
+        Some(message) => (70, message.as_str()),
+        None => (0, ""),
+    };
+    if output.status.code() != Some(code) {
+        return Err(format!("expected exit code {}, got {:?}\n{}", code, output.status.code(), stderr));
+    }
+    if stderr.lines().next().unwrap_or("") != message {
+        return Err(format!("expected error {:?}, got {:?}", message, stderr));
+    }
+    let trace: Vec<&str> = stderr.lines().skip(1).collect();
+    if !expectation.trace.is_empty() && trace != expectation.trace {
+        return Err(format!("expected trace {:?}, got {:?}", expectation.trace, trace));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_programs() {
+    let mut files = Vec::new();
+    lox_files(Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/test")), &mut files);
+    files.sort();
+
+    let mut failures = Vec::new();
+    let mut checked = 0;
+    for path in &files {
+        let source = fs::read_to_string(path).unwrap();
+        let Some(expectation) = Expectation::parse(&source) else {
+            continue;
+        };
+        for (backend, flags) in BACKENDS {
+            if expectation.skipped.iter().any(|skipped| skipped == backend) {
+                continue;
+            }
+            checked += 1;
+            if let Err(reason) = check(path, flags, &expectation) {
+                failures.push(format!("{} ({}): {}", path.display(), flags.join(" "), reason));
+            }
+        }
+    }
+
+    assert!(checked > 0, "没有找到任何测试程序");
+    assert!(failures.is_empty(), "{} 个测试程序失败:\n{}", failures.len(), failures.join("\n"));
+}
+
+#[test]
+fn test_unexpected_character_at_end_of_input() {
+    // 最后一个字节是非法字符时（REPL 中的每一行都没有换行符），错误应该报告在该字符处
+    let path = std::env::temp_dir().join(format!("rlox-trailing-{}.lox", std::process::id()));
+    fs::write(&path, "print 1 + ~").unwrap();
+    let expectation = Expectation {
+        errors: vec!["[line 1] Error at '~': Unexpected character.".to_string()],
+        ..Expectation::default()
+    };
+
+    let failures: Vec<String> = BACKENDS
+        .iter()
+        .filter_map(|(_, flags)| check(&path, flags, &expectation).err())
+        .collect();
+    fs::remove_file(&path).unwrap();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn test_field_growth_triggers_collection() {
+    // 实例分配时很小，只有字段的增长能让堆超过第一次回收的阈值
+    let path = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/test/gc/field_growth.lox"));
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .args(["--backend=vm", "--log-gc"])
+        .arg(path)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "31\n");
+    let grew = stderr.lines().any(|line| line.contains(" grow ") && line.ends_with(" for instance"));
+    assert!(grew, "字段的增长应该记录在日志中");
+    assert!(stderr.lines().any(|line| line == "-- gc begin"), "字段的增长应该触发垃圾回收");
+}